@@ -0,0 +1,18 @@
+//! ヘッダー、ブロックヘッダー、セルの読み込み
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use xrain::{read_block_header, read_cell, read_header, write_header, HEADER_SIZE};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((rest, header)) = read_header(data) {
+        assert_eq!(rest.len(), data.len() - HEADER_SIZE);
+        // 予備領域も含めてそのまま書き戻せる。
+        let mut bytes = Vec::new();
+        write_header(&mut bytes, &header).unwrap();
+        assert_eq!(bytes, data[..HEADER_SIZE]);
+        assert_eq!(read_header(&bytes).unwrap().1, header);
+    }
+    let _ = read_block_header(data);
+    let _ = read_cell(data);
+});
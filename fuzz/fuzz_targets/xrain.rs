@@ -0,0 +1,30 @@
+//! ファイル全体の読み込み
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use xrain::{
+    read_xrain, validate, MeshIndex, StreamEvent, XrainDataset, XrainStreamParser, XrainView,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(dataset) = read_xrain(data) {
+        // 読めたデータは書き出して読み戻せる。
+        let bytes = dataset.to_bytes().unwrap();
+        let decoded = XrainDataset::from_bytes(&bytes).unwrap();
+        assert!(decoded.secondary_meshes().eq(dataset.secondary_meshes()));
+    }
+    let _ = validate(data);
+    let _ = MeshIndex::from_bytes(data);
+    if let Ok(view) = XrainView::new(data) {
+        let _ = view.to_dataset();
+    }
+
+    let mut parser = XrainStreamParser::new();
+    parser.feed(data);
+    while let Ok(event) = parser.next_event() {
+        if matches!(event, StreamEvent::Incomplete(_) | StreamEvent::Done) {
+            break;
+        }
+    }
+    let _ = parser.finish();
+});
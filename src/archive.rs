@@ -0,0 +1,326 @@
+//! 配信アーカイブ(.tar, .tar.gz, .gz, .zip)からの読み込み
+//!
+//! ディスクに展開せず、エントリーを1つずつメモリ上でパースする。
+
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::{read_xrain_from, XrainDataset, XrainError};
+
+/// Container format of an archive.
+///
+/// アーカイブの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// .tar
+    Tar,
+    /// .tar.gz, .tgz
+    TarGz,
+    /// .gz(XRAINファイル1つを圧縮したもの)
+    Gzip,
+    /// .zip
+    Zip,
+}
+
+impl ArchiveKind {
+    /// ファイル名の拡張子から判定する。
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let name = path.as_ref().file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".gz") {
+            Some(ArchiveKind::Gzip)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// 先頭のバイト列から判定する。gzipの場合は展開した先頭がtarかどうかも見る。
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"PK\x03\x04") {
+            return Some(ArchiveKind::Zip);
+        }
+        if head.starts_with(&[0x1F, 0x8B]) {
+            let mut inner = Vec::with_capacity(TAR_MAGIC_END);
+            // 先頭だけを展開するので途中で終わるエラーは無視する。
+            let _ = GzDecoder::new(head)
+                .take(TAR_MAGIC_END as u64)
+                .read_to_end(&mut inner);
+            return Some(if is_tar(&inner) {
+                ArchiveKind::TarGz
+            } else {
+                ArchiveKind::Gzip
+            });
+        }
+        is_tar(head).then_some(ArchiveKind::Tar)
+    }
+}
+
+/// tarヘッダーの"ustar"の終わりの位置
+const TAR_MAGIC_END: usize = 262;
+/// 判定に読む先頭のバイト数
+const SNIFF_SIZE: usize = 1024;
+
+fn is_tar(head: &[u8]) -> bool {
+    head.len() >= TAR_MAGIC_END && &head[257..TAR_MAGIC_END] == b"ustar"
+}
+
+enum Inner<R: Read + Seek> {
+    Tar(tar::Archive<R>),
+    TarGz(tar::Archive<GzDecoder<R>>),
+    Gzip(Option<GzDecoder<R>>),
+    Zip(ZipArchive<R>),
+}
+
+/// An archive of XRAIN files.
+///
+/// XRAINファイルをまとめたアーカイブ
+pub struct XrainArchive<R: Read + Seek> {
+    inner: Inner<R>,
+    /// .gzの場合のエントリー名
+    name: String,
+}
+
+impl XrainArchive<File> {
+    /// アーカイブファイルを開く。形式は中身から判定し、判定できなければ拡張子から決める。
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, XrainError> {
+        let path = path.as_ref();
+        let mut archive = match Self::new(File::open(path)?) {
+            Err(XrainError::UnsupportedArchive) => {
+                let kind = ArchiveKind::from_path(path).ok_or(XrainError::UnsupportedArchive)?;
+                Self::with_kind(File::open(path)?, kind)?
+            }
+            other => other?,
+        };
+        if let Inner::Gzip(_) = archive.inner {
+            // a.gz -> a
+            if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                archive.name = stem.into();
+            }
+        }
+        Ok(archive)
+    }
+}
+
+impl<'a> XrainArchive<Cursor<&'a [u8]>> {
+    /// メモリ上のアーカイブ
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, XrainError> {
+        Self::new(Cursor::new(bytes))
+    }
+}
+
+impl<R: Read + Seek> XrainArchive<R> {
+    /// 先頭から形式を判定して開く。
+    pub fn new(mut reader: R) -> Result<Self, XrainError> {
+        let mut head = Vec::with_capacity(SNIFF_SIZE);
+        (&mut reader)
+            .take(SNIFF_SIZE as u64)
+            .read_to_end(&mut head)?;
+        reader.seek(SeekFrom::Start(0))?;
+        let kind = ArchiveKind::detect(&head).ok_or(XrainError::UnsupportedArchive)?;
+        Self::with_kind(reader, kind)
+    }
+
+    /// 形式を指定して開く。
+    pub fn with_kind(reader: R, kind: ArchiveKind) -> Result<Self, XrainError> {
+        let mut name = String::new();
+        let inner = match kind {
+            ArchiveKind::Tar => Inner::Tar(tar::Archive::new(reader)),
+            ArchiveKind::TarGz => Inner::TarGz(tar::Archive::new(GzDecoder::new(reader))),
+            ArchiveKind::Gzip => {
+                let decoder = GzDecoder::new(reader);
+                if let Some(file_name) = decoder.header().and_then(|h| h.filename()) {
+                    name = String::from_utf8_lossy(file_name).into();
+                }
+                Inner::Gzip(Some(decoder))
+            }
+            ArchiveKind::Zip => Inner::Zip(ZipArchive::new(reader).map_err(std::io::Error::from)?),
+        };
+        Ok(Self { inner, name })
+    }
+
+    /// アーカイブの形式
+    pub fn kind(&self) -> ArchiveKind {
+        match self.inner {
+            Inner::Tar(_) => ArchiveKind::Tar,
+            Inner::TarGz(_) => ArchiveKind::TarGz,
+            Inner::Gzip(_) => ArchiveKind::Gzip,
+            Inner::Zip(_) => ArchiveKind::Zip,
+        }
+    }
+
+    /// エントリー名とパース結果を順に返す。ディレクトリは飛ばす。
+    /// パースできないエントリーは`Err`を返すが、続けて次のエントリーを読める。
+    /// tarとgzは先頭から一度しか読めない。
+    pub fn entries(&mut self) -> Result<ArchiveEntries<'_, R>, XrainError> {
+        let inner = match &mut self.inner {
+            Inner::Tar(archive) => EntriesInner::Tar(archive.entries()?),
+            Inner::TarGz(archive) => EntriesInner::TarGz(archive.entries()?),
+            Inner::Gzip(decoder) => EntriesInner::Gzip(decoder.take(), &self.name),
+            Inner::Zip(archive) => EntriesInner::Zip(archive, 0),
+        };
+        Ok(ArchiveEntries { inner })
+    }
+}
+
+enum EntriesInner<'a, R: 'a + Read + Seek> {
+    Tar(tar::Entries<'a, R>),
+    TarGz(tar::Entries<'a, GzDecoder<R>>),
+    Gzip(Option<GzDecoder<R>>, &'a str),
+    Zip(&'a mut ZipArchive<R>, usize),
+}
+
+/// Iterator over the files in an [`XrainArchive`].
+///
+/// アーカイブ内のファイルを読むイテレーター
+pub struct ArchiveEntries<'a, R: 'a + Read + Seek> {
+    inner: EntriesInner<'a, R>,
+}
+
+/// tarのエントリーを1つ読む。ディレクトリなどファイル以外は`None`。
+fn read_tar_entry<T: Read>(
+    entry: std::io::Result<tar::Entry<'_, T>>,
+) -> Option<Result<(String, XrainDataset), XrainError>> {
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(e) => return Some(Err(e.into())),
+    };
+    if !entry.header().entry_type().is_file() {
+        return None;
+    }
+    let name = match entry.path() {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(e) => return Some(Err(e.into())),
+    };
+    Some(read_xrain_from(entry).map(|dataset| (name, dataset)))
+}
+
+impl<'a, R: 'a + Read + Seek> Iterator for ArchiveEntries<'a, R> {
+    type Item = Result<(String, XrainDataset), XrainError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            EntriesInner::Tar(entries) => entries.find_map(read_tar_entry),
+            EntriesInner::TarGz(entries) => entries.find_map(read_tar_entry),
+            EntriesInner::Gzip(decoder, name) => {
+                let decoder = decoder.take()?;
+                Some(read_xrain_from(decoder).map(|dataset| (name.to_string(), dataset)))
+            }
+            EntriesInner::Zip(archive, index) => loop {
+                if *index >= archive.len() {
+                    return None;
+                }
+                let file = archive.by_index(*index);
+                *index += 1;
+                let file = match file {
+                    Ok(file) => file,
+                    Err(e) => return Some(Err(std::io::Error::from(e).into())),
+                };
+                if file.is_dir() {
+                    continue;
+                }
+                let name = file.name().to_string();
+                return Some(read_xrain_from(file).map(|dataset| (name, dataset)));
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+    use crate::ObservationTime;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// ブロックのないXRAINファイル
+    fn xrain_bytes(minute: u8) -> Vec<u8> {
+        Fixture::default()
+            .datetime(ObservationTime::new(2019, 10, 11, 0, minute, 0).unwrap())
+            .blocks([])
+            .build()
+    }
+
+    fn tar_bytes() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, minute) in [("a/KANTO00001-20191011-0000-G000-EL000000", 0), ("a/b", 1)] {
+            let data = xrain_bytes(minute);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn minutes(archive: &[u8], kind: ArchiveKind) -> Vec<(String, u8)> {
+        let mut archive = XrainArchive::from_bytes(archive).unwrap();
+        assert_eq!(archive.kind(), kind);
+        archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap())
+            .map(|(name, dataset)| (name, dataset.header().datetime().minute()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tar() {
+        let expected = vec![
+            ("a/KANTO00001-20191011-0000-G000-EL000000".to_string(), 0),
+            ("a/b".to_string(), 1),
+        ];
+        let tar = tar_bytes();
+        assert_eq!(minutes(&tar, ArchiveKind::Tar), expected);
+        assert_eq!(minutes(&gzip(&tar), ArchiveKind::TarGz), expected);
+    }
+
+    #[test]
+    fn test_gzip_and_zip() {
+        let gz = gzip(&xrain_bytes(5));
+        assert_eq!(minutes(&gz, ArchiveKind::Gzip), vec![(String::new(), 5)]);
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        writer.add_directory("dir/", options).unwrap();
+        writer.start_file("dir/x", options).unwrap();
+        writer.write_all(&xrain_bytes(7)).unwrap();
+        writer.start_file("broken", options).unwrap();
+        writer.write_all(b"broken").unwrap();
+        let zip = writer.finish().unwrap().into_inner();
+
+        let mut archive = XrainArchive::from_bytes(&zip).unwrap();
+        let entries: Vec<_> = archive.entries().unwrap().collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].as_ref().unwrap().0, "dir/x");
+        assert!(matches!(entries[1], Err(XrainError::BadMagic { .. })));
+
+        assert!(matches!(
+            XrainArchive::from_bytes(b"plain"),
+            Err(XrainError::UnsupportedArchive)
+        ));
+        assert_eq!(
+            ArchiveKind::from_path("x/KANTO.tar.gz"),
+            Some(ArchiveKind::TarGz)
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! ディレクトリ内のXRAINファイルの目録
+//!
+//! ファイルはヘッダーだけを読む。ブロックは読まないので大量のファイルでも速い。
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    read_header, ObservationTime, PrimaryMeshCode, Region, XrainError, XrainFileName, XrainHeader,
+    HEADER_SIZE,
+};
+
+/// Expected interval between consecutive files.
+///
+/// 観測間隔
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cadence {
+    /// 1分間隔
+    #[default]
+    OneMinute,
+    /// 5分間隔
+    FiveMinutes,
+}
+
+impl Cadence {
+    /// 間隔(分)
+    pub fn minutes(&self) -> i64 {
+        match self {
+            Cadence::OneMinute => 1,
+            Cadence::FiveMinutes => 5,
+        }
+    }
+}
+
+/// A file found by [`Catalog::scan`].
+///
+/// 目録の1ファイル分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    path: PathBuf,
+    name: Option<XrainFileName>,
+    header: XrainHeader,
+}
+
+impl CatalogEntry {
+    /// ファイルのパス
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// ファイル名の情報。XRAINのファイル名でなければ`None`。
+    pub fn name(&self) -> Option<&XrainFileName> {
+        self.name.as_ref()
+    }
+
+    /// ファイルのヘッダー
+    pub fn header(&self) -> &XrainHeader {
+        &self.header
+    }
+
+    /// 観測日時(日本標準時)
+    pub fn datetime(&self) -> ObservationTime {
+        self.header.datetime()
+    }
+
+    /// 地整。ヘッダーの地整識別コードが未知ならファイル名から求める。
+    pub fn region(&self) -> Option<Region> {
+        self.header
+            .region()
+            .or_else(|| self.name.as_ref().map(|n| n.region()))
+    }
+
+    /// 南西端から北東端の範囲に1次メッシュが含まれるか。
+    pub fn covers(&self, code: PrimaryMeshCode) -> bool {
+        self.header.covers(code)
+    }
+}
+
+/// Conditions for [`Catalog::query`]. Unset conditions match every file.
+///
+/// 目録の検索条件。指定しなかった条件はすべてのファイルに一致する。
+#[derive(Debug, Clone, Default)]
+pub struct CatalogQuery {
+    start: Option<ObservationTime>,
+    end: Option<ObservationTime>,
+    region: Option<Region>,
+    mesh: Option<PrimaryMeshCode>,
+}
+
+impl CatalogQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 観測日時がstart以上end未満
+    pub fn between(mut self, start: ObservationTime, end: ObservationTime) -> Self {
+        self.start = Some(start);
+        self.end = Some(end);
+        self
+    }
+
+    /// 地整
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// 1次メッシュを範囲に含む
+    pub fn covering(mut self, mesh: PrimaryMeshCode) -> Self {
+        self.mesh = Some(mesh);
+        self
+    }
+
+    /// ファイルが条件に一致するか。
+    pub fn matches(&self, entry: &CatalogEntry) -> bool {
+        let time = entry.datetime();
+        self.start.iter().all(|&start| start <= time)
+            && self.end.iter().all(|&end| time < end)
+            && self.region.iter().all(|&r| entry.region() == Some(r))
+            && self.mesh.iter().all(|&m| entry.covers(m))
+    }
+}
+
+/// A run of missing observations between two files.
+///
+/// 欠けている観測時刻の範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// 地整。ヘッダーの地整識別コードからもファイル名からも分からなければ`None`。
+    pub region: Option<Region>,
+    /// 地整識別コード
+    pub owner: u8,
+    /// 欠ける直前のファイルの観測日時
+    pub after: ObservationTime,
+    /// 欠けた直後のファイルの観測日時
+    pub before: ObservationTime,
+    /// 欠けている観測の数
+    pub missing: u32,
+}
+
+/// Header-only index of the XRAIN files under a directory.
+///
+/// ディレクトリ以下のXRAINファイルの目録。観測日時順に並ぶ。
+#[derive(Debug, Default)]
+pub struct Catalog {
+    entries: Vec<CatalogEntry>,
+    skipped: Vec<(PathBuf, XrainError)>,
+}
+
+impl Catalog {
+    /// ディレクトリ以下を再帰的に探してヘッダーを読む。
+    /// ヘッダーが読めないファイルは`skipped`に入れて続ける。ディレクトリが読めない場合はエラー。
+    /// ディレクトリへのシンボリックリンクはたどらない。
+    pub fn scan<P: AsRef<Path>>(dir: P) -> Result<Self, XrainError> {
+        let mut catalog = Catalog::default();
+        let mut dirs = vec![dir.as_ref().to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                // DirEntry::file_typeはシンボリックリンクをたどらない
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if file_type.is_symlink() && path.is_dir() {
+                    continue;
+                }
+                match read_header_file(&path) {
+                    Ok(header) => catalog.push(path, header),
+                    Err(e) => catalog.skipped.push((path, e)),
+                }
+            }
+        }
+        catalog.sort();
+        Ok(catalog)
+    }
+
+    /// ヘッダーを読み済みのファイルを追加する。追加し終えたら`sort`を呼ぶ。
+    pub(crate) fn push(&mut self, path: PathBuf, header: XrainHeader) {
+        let name = XrainFileName::from_path(&path).ok();
+        self.entries.push(CatalogEntry { path, name, header });
+    }
+
+    /// 観測日時順、同じ日時ならパス順に並べ直す。
+    fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| (a.datetime(), &a.path).cmp(&(b.datetime(), &b.path)));
+    }
+
+    /// すべてのファイル(観測日時順)
+    pub fn entries(&self) -> &[CatalogEntry] {
+        &self.entries
+    }
+
+    /// ヘッダーが読めなかったファイルとその理由
+    pub fn skipped(&self) -> &[(PathBuf, XrainError)] {
+        &self.skipped
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 条件に一致するファイル(観測日時順)
+    pub fn query<'a>(&'a self, query: &'a CatalogQuery) -> impl Iterator<Item = &'a CatalogEntry> {
+        self.entries.iter().filter(move |e| query.matches(e))
+    }
+
+    /// 条件に一致するファイルの観測日時を`cadence`間隔で並べたときの抜け。
+    /// 地整(地整とその識別コードの組)ごとに調べ、欠けた直後の観測日時順に並べる。
+    /// 同じ観測日時のファイルは1つと数える。範囲の前後の抜けは調べない。
+    pub fn gaps(&self, query: &CatalogQuery, cadence: Cadence) -> Vec<Gap> {
+        let step = cadence.minutes();
+        let mut gaps = Vec::new();
+        let mut previous: HashMap<(Option<Region>, u8), ObservationTime> = HashMap::new();
+        for entry in self.query(query) {
+            let time = entry.datetime();
+            let region = entry.region();
+            let owner = entry.header().owner();
+            if let Some(prev) = previous.insert((region, owner), time) {
+                let minutes = (time.to_jst() - prev.to_jst()).num_minutes();
+                if minutes > step {
+                    gaps.push(Gap {
+                        region,
+                        owner,
+                        after: prev,
+                        before: time,
+                        missing: ((minutes - 1) / step) as u32,
+                    });
+                }
+            }
+        }
+        gaps
+    }
+}
+
+/// ファイルの先頭のヘッダーだけを読む。
+fn read_header_file(path: &Path) -> Result<XrainHeader, XrainError> {
+    let mut buf = Vec::with_capacity(HEADER_SIZE);
+    File::open(path)?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut buf)?;
+    read_header(&buf).map(|(_, header)| header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    fn header(minute: u8, owner: u8) -> XrainHeader {
+        XrainHeader::builder()
+            .owner(owner)
+            .data_kind3(u16::from(owner))
+            .datetime(ObservationTime::new(2019, 10, 11, 0, minute, 0).unwrap())
+            .extent("4634".parse().unwrap(), "5543".parse().unwrap())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_query_and_gaps() {
+        let mut catalog = Catalog::default();
+        for minute in [0, 1, 2, 5, 6, 10] {
+            let name = format!("KANTO00001-20191011-00{:02}-G000-EL000000", minute);
+            catalog.push(name.into(), header(minute, 71));
+        }
+        // 関東以外の地整識別コード
+        catalog.push("other".into(), header(3, 0));
+        catalog.sort();
+
+        let kanto = CatalogQuery::new().region(Region::Kanto);
+        assert_eq!(catalog.query(&kanto).count(), 6);
+        let range = CatalogQuery::new().between(
+            ObservationTime::new(2019, 10, 11, 0, 1, 0).unwrap(),
+            ObservationTime::new(2019, 10, 11, 0, 6, 0).unwrap(),
+        );
+        assert_eq!(catalog.query(&range).count(), 4);
+        let mesh = CatalogQuery::new().covering("5339".parse().unwrap());
+        assert_eq!(catalog.query(&mesh).count(), 7);
+        let mesh = CatalogQuery::new().covering("6040".parse().unwrap());
+        assert_eq!(catalog.query(&mesh).count(), 0);
+
+        let gaps = catalog.gaps(&kanto, Cadence::OneMinute);
+        let missing: Vec<(u8, u32)> = gaps.iter().map(|g| (g.after.minute(), g.missing)).collect();
+        assert_eq!(missing, vec![(2, 2), (6, 3)]);
+        assert!(catalog.gaps(&kanto, Cadence::FiveMinutes).is_empty());
+
+        // 地整を指定しなくても、他の地整のファイルで抜けが埋まらない
+        let all = catalog.gaps(&CatalogQuery::new(), Cadence::OneMinute);
+        assert_eq!(all, gaps);
+        assert_eq!(all[0].region, Some(Region::Kanto));
+        assert_eq!(all[0].owner, 71);
+    }
+
+    #[test]
+    fn test_scan() -> Result<(), XrainError> {
+        let dir = std::env::temp_dir().join(format!("xrain-catalog-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub"))?;
+        let bytes = Fixture::default()
+            .datetime(ObservationTime::new(2019, 10, 11, 0, 5, 0).unwrap())
+            .build();
+        std::fs::write(
+            dir.join("sub/KANTO00001-20191011-0005-G000-EL000000"),
+            bytes,
+        )?;
+        std::fs::write(dir.join("README"), b"not xrain")?;
+        // 親ディレクトリへのリンクをたどると終わらない
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop"))?;
+
+        let catalog = Catalog::scan(&dir)?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(catalog.skipped().len(), 1);
+        let entry = &catalog.entries()[0];
+        assert_eq!(entry.datetime().minute(), 5);
+        assert!(entry.name().unwrap().matches_header(entry.header()));
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use std::path::Path;
+
+use crate::{
+    open_xrain, read_xrain, read_xrain_from, Array2, Array3, BTreeMap, PrimaryMesh,
+    PrimaryMeshCode, QuarterTertiaryMeshCode, Read, SecondaryMesh, SecondaryMeshCode,
+    XrainBlockHeader, XrainCell, XrainError, XrainHeader,
+};
+
+/// A parsed XRAIN file: the header and every secondary mesh in it.
+///
+/// XRAINファイル1つ分のデータ。ヘッダーと1次メッシュごとにまとめた2次メッシュを持つ。
+/// 比較はヘッダーと2次メッシュだけで、ブロック構成は比べない。
+#[derive(Debug, Clone)]
+pub struct XrainDataset {
+    header: XrainHeader,
+    /// 1次メッシュコードをキーにする2分木
+    primary: BTreeMap<PrimaryMeshCode, PrimaryMesh>,
+    /// 読んだファイルのブロック構成(ファイル順)。2次メッシュから作成した場合は空。
+    blocks: Vec<XrainBlockHeader>,
+}
+
+impl PartialEq for XrainDataset {
+    fn eq(&self, other: &Self) -> bool {
+        self.header == other.header && self.primary == other.primary
+    }
+}
+
+impl Eq for XrainDataset {}
+
+impl XrainDataset {
+    /// ヘッダーと2次メッシュから作成する。同じコードの2次メッシュは後のものが残る。
+    /// セル数がヘッダーのメッシュの大きさと異なる2次メッシュがあれば`XrainError::CellCountMismatch`。
+    pub fn new<I>(header: XrainHeader, meshes: I) -> Result<Self, XrainError>
+    where
+        I: IntoIterator<Item = SecondaryMesh>,
+    {
+        let expected = header.mesh_kind().cells_per_mesh();
+        let mut primary: BTreeMap<PrimaryMeshCode, PrimaryMesh> = BTreeMap::new();
+        for mesh in meshes {
+            if mesh.cells().len() != expected {
+                return Err(XrainError::CellCountMismatch {
+                    code: mesh.code(),
+                    expected,
+                    found: mesh.cells().len(),
+                });
+            }
+            let code = mesh.code().primary();
+            primary
+                .entry(code)
+                .or_insert_with(|| PrimaryMesh::new(code))
+                .insert(mesh);
+        }
+        Ok(Self {
+            header,
+            primary,
+            blocks: Vec::new(),
+        })
+    }
+
+    /// 読んだファイルのブロック構成を持たせる。
+    pub(crate) fn with_blocks(mut self, blocks: Vec<XrainBlockHeader>) -> Self {
+        self.blocks = blocks;
+        self
+    }
+
+    /// ファイルを開いてパースする。
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, XrainError> {
+        open_xrain(path)
+    }
+
+    /// メモリ上のファイル1つ分をパースする。
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, XrainError> {
+        read_xrain(bytes)
+    }
+
+    /// `reader`を最後まで読んでパースする。読み込みに失敗した場合は`XrainError::Io`。
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, XrainError> {
+        read_xrain_from(reader)
+    }
+
+    /// ファイルのヘッダー
+    pub fn header(&self) -> &XrainHeader {
+        &self.header
+    }
+
+    /// 読んだファイルのブロック構成(ファイル順)。2次メッシュから作成した場合は空。
+    pub fn blocks(&self) -> &[XrainBlockHeader] {
+        &self.blocks
+    }
+
+    /// 1次メッシュコードで取得する。
+    pub fn primary(&self, code: PrimaryMeshCode) -> Option<&PrimaryMesh> {
+        self.primary.get(&code)
+    }
+
+    /// 2次メッシュコードで取得する。
+    pub fn secondary(&self, code: SecondaryMeshCode) -> Option<&SecondaryMesh> {
+        self.primary.get(&code.primary())?.get(code)
+    }
+
+    /// 1/4 3次メッシュコードのセルを取得する。
+    pub fn cell(&self, code: QuarterTertiaryMeshCode) -> Option<&XrainCell> {
+        self.secondary(code.secondary())?.cell_at(code)
+    }
+
+    /// データのある1次メッシュ(コード順)
+    pub fn primary_meshes(&self) -> impl Iterator<Item = &PrimaryMesh> {
+        self.primary.values()
+    }
+
+    /// データのあるすべての2次メッシュ(コード順)
+    pub fn secondary_meshes(&self) -> impl Iterator<Item = &SecondaryMesh> {
+        self.primary.values().flat_map(|p| p.secondary())
+    }
+
+    /// データのある1次メッシュコード
+    pub fn primary_codes(&self) -> impl Iterator<Item = PrimaryMeshCode> + '_ {
+        self.primary.keys().copied()
+    }
+
+    /// 2次メッシュのデータがあるか。
+    pub fn contains(&self, code: SecondaryMeshCode) -> bool {
+        self.secondary(code).is_some()
+    }
+
+    /// 1次メッシュ内でデータのある2次メッシュの割合。1次メッシュがなければ0。
+    pub fn coverage(&self, primary: PrimaryMeshCode) -> f64 {
+        self.primary.get(&primary).map_or(0.0, |p| p.coverage())
+    }
+
+    /// 2次メッシュの数
+    pub fn len(&self) -> usize {
+        self.primary.values().map(|p| p.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primary.is_empty()
+    }
+
+    /// 1次メッシュの雨量と品質の(2, 320, 320)配列。
+    pub fn primary_ndarray(&self, code: PrimaryMeshCode) -> Option<Array3<u16>> {
+        self.primary.get(&code).map(|p| p.to_ndarray())
+    }
+
+    /// 1次メッシュの雨量(mm/h)の(320, 320)配列。特殊値とデータのない2次メッシュはNaN。
+    pub fn primary_rain_mm(&self, code: PrimaryMeshCode) -> Option<Array2<f64>> {
+        self.primary.get(&code).map(|p| p.rain_mm_ndarray())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    fn mesh(code: &str, strength: u16) -> SecondaryMesh {
+        SecondaryMesh::new(
+            code.parse().unwrap(),
+            vec![XrainCell::new(0, strength); 1600],
+        )
+        .unwrap()
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::ConnectionReset.into())
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_and_reader() {
+        let data = Fixture::default().blocks([("543870", 1)]).build();
+        let dataset = XrainDataset::from_bytes(&data).unwrap();
+        assert!(dataset.contains("543870".parse().unwrap()));
+        assert_eq!(XrainDataset::from_reader(data.as_slice()).unwrap(), dataset);
+
+        assert!(matches!(
+            XrainDataset::from_bytes(&data[..100]),
+            Err(XrainError::Truncated { offset: 100, .. })
+        ));
+        match XrainDataset::from_reader(FailingReader) {
+            Err(XrainError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            XrainDataset::open("no/such/file"),
+            Err(XrainError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let dataset = XrainDataset::new(
+            Fixture::default().header(),
+            vec![mesh("543870", 1), mesh("543807", 2), mesh("533933", 3)],
+        )
+        .unwrap();
+        assert_eq!(dataset.len(), 3);
+        assert_eq!(
+            dataset
+                .primary_codes()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>(),
+            vec!["5339", "5438"]
+        );
+        assert!(dataset.contains("543870".parse().unwrap()));
+        assert!(!dataset.contains("543877".parse().unwrap()));
+        let code: SecondaryMeshCode = "533933".parse().unwrap();
+        assert_eq!(dataset.secondary(code).unwrap().cells()[0].strength(), 3);
+        assert_eq!(
+            dataset.cell(code.cell(39, 39).unwrap()).unwrap().strength(),
+            3
+        );
+        assert_eq!(dataset.coverage("5438".parse().unwrap()), 2.0 / 64.0);
+        assert_eq!(dataset.coverage("5440".parse().unwrap()), 0.0);
+
+        // ファイルに書けない大きさの2次メッシュは黙って捨てずにエラーにする
+        let half = SecondaryMesh::new(code, vec![XrainCell::default(); 400]).unwrap();
+        assert!(matches!(
+            XrainDataset::new(Fixture::default().header(), vec![mesh("543870", 1), half]),
+            Err(XrainError::CellCountMismatch {
+                expected: 1600,
+                found: 400,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_primary_ndarray() {
+        let dataset = XrainDataset::new(
+            Fixture::default().header(),
+            vec![mesh("543870", 1), mesh("543807", 2)],
+        )
+        .unwrap();
+        let arr = dataset.primary_ndarray("5438".parse().unwrap()).unwrap();
+        assert_eq!(arr.shape(), &[2, 320, 320]);
+        // 北西端
+        assert_eq!(arr[[0, 0, 0]], 1);
+        // 南東端
+        assert_eq!(arr[[0, 319, 319]], 2);
+        assert_eq!(arr[[0, 160, 160]], 0);
+        assert!(dataset.primary_ndarray("5440".parse().unwrap()).is_none());
+
+        let rain = dataset.primary_rain_mm("5438".parse().unwrap()).unwrap();
+        assert!((rain[[0, 0]] - 0.1).abs() < 1e-9);
+        assert!(rain[[160, 160]].is_nan());
+    }
+}
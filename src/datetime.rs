@@ -0,0 +1,153 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+/// 日本標準時(UTC+9)の秒数
+const JST_OFFSET_SECS: i32 = 9 * 3600;
+
+/// BCD4桁で書ける最大の年
+const MAX_YEAR: u16 = 9999;
+
+/// Observation date and time of a file, in JST.
+///
+/// 観測日時(日本標準時)
+/// ヘッダーの16byteのうち先頭7byteが年(2byte)、月、日、時、分、秒のBCD。残りは予備。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ObservationTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl ObservationTime {
+    /// 日時から作成する。実在しない日時か、年がBCD4桁に収まらない場合は`None`。
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        if year > MAX_YEAR {
+            return None;
+        }
+        let time = Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+        time.to_naive().map(|_| time)
+    }
+
+    /// ヘッダーの観測日時16byteを読む。BCDでないか実在しない日時の場合は`None`。
+    pub(crate) fn from_bcd(bytes: &[u8]) -> Option<Self> {
+        let mut digits = [0u8; 7];
+        for (digit, byte) in digits.iter_mut().zip(bytes.iter()) {
+            let upper = byte >> 4;
+            let lower = byte & 0x0F;
+            if upper > 9 || lower > 9 {
+                return None;
+            }
+            *digit = upper * 10 + lower;
+        }
+        let year = u16::from(digits[0]) * 100 + u16::from(digits[1]);
+        Self::new(year, digits[2], digits[3], digits[4], digits[5], digits[6])
+    }
+
+    /// ヘッダーの観測日時16byteに書く。予備の9byteは0。
+    pub(crate) fn to_bcd(self) -> [u8; 16] {
+        let digits = [
+            (self.year / 100) as u8,
+            (self.year % 100) as u8,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        ];
+        let mut bytes = [0u8; 16];
+        for (byte, digit) in bytes.iter_mut().zip(digits) {
+            *byte = crate::to_bcd(digit);
+        }
+        bytes
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    fn to_naive(self) -> Option<NaiveDateTime> {
+        NaiveDate::from_ymd_opt(self.year.into(), self.month.into(), self.day.into())?.and_hms_opt(
+            self.hour.into(),
+            self.minute.into(),
+            self.second.into(),
+        )
+    }
+
+    /// 日本標準時のタイムスタンプ
+    pub fn to_jst(&self) -> DateTime<FixedOffset> {
+        let jst = FixedOffset::east_opt(JST_OFFSET_SECS).unwrap();
+        // newとfrom_bcdで検証済み。Default(0000-00-00)の場合は1970-01-01になる。
+        let naive = self.to_naive().unwrap_or_default();
+        jst.from_local_datetime(&naive).unwrap()
+    }
+
+    /// 協定世界時のタイムスタンプ
+    pub fn to_utc(&self) -> DateTime<Utc> {
+        self.to_jst().with_timezone(&Utc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bcd() {
+        let mut bytes = [0u8; 16];
+        bytes[..7].copy_from_slice(&[0x20, 0x19, 0x10, 0x11, 0x00, 0x05, 0x30]);
+        let time = ObservationTime::from_bcd(&bytes).unwrap();
+        assert_eq!(time, ObservationTime::new(2019, 10, 11, 0, 5, 30).unwrap());
+        assert_eq!(time.to_jst().to_rfc3339(), "2019-10-11T00:05:30+09:00");
+        assert_eq!(time.to_utc().to_rfc3339(), "2019-10-10T15:05:30+00:00");
+        assert_eq!(time.to_bcd(), bytes);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(ObservationTime::new(2019, 2, 29, 0, 0, 0).is_none());
+        assert!(ObservationTime::new(2019, 10, 11, 24, 0, 0).is_none());
+        let bytes = [0x20, 0x19, 0x1A, 0x11, 0x00, 0x00, 0x00];
+        assert!(ObservationTime::from_bcd(&bytes).is_none());
+        assert!(ObservationTime::new(10000, 1, 1, 0, 0, 0).is_none());
+    }
+
+    #[test]
+    fn test_max_year() {
+        let time = ObservationTime::new(9999, 12, 31, 23, 59, 59).unwrap();
+        let bytes = time.to_bcd();
+        assert_eq!(bytes[..7], [0x99, 0x99, 0x12, 0x31, 0x23, 0x59, 0x59]);
+        assert_eq!(ObservationTime::from_bcd(&bytes), Some(time));
+        let data = crate::fixture::Fixture::default().datetime(time).build();
+        assert_eq!(crate::read_header(&data).unwrap().1.datetime(), time);
+    }
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+
+use crate::{PrimaryMeshCode, Region, SecondaryMeshCode};
+
+/// XRAINファイルのどの部分を読んでいたか。
+///
+/// Which part of the file was being decoded when an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// ファイルヘッダー
+    Header,
+    /// ブロックヘッダー
+    BlockHeader,
+    /// 2次メッシュ内のセル
+    Cell,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Section::Header => write!(f, "header"),
+            Section::BlockHeader => write!(f, "block header"),
+            Section::Cell => write!(f, "cell"),
+        }
+    }
+}
+
+/// Errors returned while reading or writing XRAIN data.
+///
+/// パースと書き出しで発生するエラー。
+/// `offset`は読み込みに渡したスライスの先頭からのバイト位置。
+#[derive(Debug)]
+pub enum XrainError {
+    /// ファイルの読み込みに失敗した。
+    Io(std::io::Error),
+    /// csvの書き出しに失敗した。
+    Csv(csv::Error),
+    /// 先頭の固定値が0xFDではない。
+    BadMagic { offset: usize, found: u8 },
+    /// データ種別1,2,3が対応していない値。
+    UnsupportedDataKind { offset: usize, found: u16 },
+    /// ヘッダ種別、観測値識別が想定外の値。
+    UnexpectedHeaderKind { offset: usize, found: u8 },
+    /// ヘッダー末尾の固定値0x0000が一致しない。
+    BadTerminator { offset: usize, found: [u8; 2] },
+    /// 観測日時がBCDでないか実在しない日時。
+    InvalidDateTime { offset: usize },
+    /// メッシュコードが範囲外。
+    InvalidMeshCode { offset: usize },
+    /// 入力が途中で終わっている。
+    Truncated {
+        section: Section,
+        offset: usize,
+        needed: usize,
+    },
+    /// 連続する2次メッシュ数が0のブロック。
+    EmptyBlock { offset: usize },
+    /// ヘッダーのブロック数と実際に読めたブロック数が一致しない。
+    BlockCountMismatch { declared: u16, found: u16 },
+    /// 対応していないアーカイブ形式。
+    UnsupportedArchive,
+    /// 地整識別コードが対応している地整(関東)のものでない。
+    UnknownOwner { found: u8 },
+    /// 南西端、北東端の1次メッシュが地整の合成範囲外。
+    RegionExtentMismatch {
+        region: Region,
+        bottom_left: PrimaryMeshCode,
+        top_right: PrimaryMeshCode,
+    },
+    /// 書き出す2次メッシュのセル数がヘッダーのメッシュの大きさと一致しない。
+    CellCountMismatch {
+        code: SecondaryMeshCode,
+        expected: usize,
+        found: usize,
+    },
+    /// 2次メッシュのセル数が1600(250m)、400(500m)、100(1km)のどれでもない。
+    InvalidCellCount {
+        code: SecondaryMeshCode,
+        found: usize,
+    },
+    /// 品質が4bit、雨量が12bitに収まらないセル。`index`は2次メッシュ内の位置。
+    CellOutOfRange {
+        code: SecondaryMeshCode,
+        index: usize,
+    },
+    /// 東へ連続していないか256個以上の2次メッシュを1つのブロックにしようとした。
+    InvalidBlock { code: SecondaryMeshCode },
+    /// ブロック数かデータサイズがヘッダーに書ける範囲を超える。
+    TooLarge,
+    /// ヘッダーを作るのに必要な項目が指定されていない。
+    MissingHeaderField { field: &'static str },
+    /// 南西端から北東端の配列が`MAX_MOSAIC_CELLS`を超える。
+    MosaicTooLarge {
+        bottom_left: PrimaryMeshCode,
+        top_right: PrimaryMeshCode,
+    },
+    /// 南西端の1次メッシュが北東端より北か東にある。
+    InvalidExtent {
+        bottom_left: PrimaryMeshCode,
+        top_right: PrimaryMeshCode,
+    },
+}
+
+impl XrainError {
+    /// Moves the byte offset by `base`, for errors from a sub-slice.
+    ///
+    /// 部分スライスで発生したエラーの位置を元のスライス基準に直す。
+    pub(crate) fn shift(self, base: usize) -> Self {
+        match self {
+            XrainError::BadMagic { offset, found } => XrainError::BadMagic {
+                offset: offset + base,
+                found,
+            },
+            XrainError::UnsupportedDataKind { offset, found } => XrainError::UnsupportedDataKind {
+                offset: offset + base,
+                found,
+            },
+            XrainError::UnexpectedHeaderKind { offset, found } => {
+                XrainError::UnexpectedHeaderKind {
+                    offset: offset + base,
+                    found,
+                }
+            }
+            XrainError::BadTerminator { offset, found } => XrainError::BadTerminator {
+                offset: offset + base,
+                found,
+            },
+            XrainError::InvalidDateTime { offset } => XrainError::InvalidDateTime {
+                offset: offset + base,
+            },
+            XrainError::InvalidMeshCode { offset } => XrainError::InvalidMeshCode {
+                offset: offset + base,
+            },
+            XrainError::Truncated {
+                section,
+                offset,
+                needed,
+            } => XrainError::Truncated {
+                section,
+                offset: offset + base,
+                needed,
+            },
+            XrainError::EmptyBlock { offset } => XrainError::EmptyBlock {
+                offset: offset + base,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for XrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XrainError::Io(e) => write!(f, "failed to read XRAIN data: {}", e),
+            XrainError::Csv(e) => write!(f, "failed to write csv: {}", e),
+            XrainError::BadMagic { offset, found } => write!(
+                f,
+                "bad magic byte 0x{:02X} at offset {} (expected 0xFD)",
+                found, offset
+            ),
+            XrainError::UnsupportedDataKind { offset, found } => write!(
+                f,
+                "unsupported data kind 0x{:02X} at offset {}",
+                found, offset
+            ),
+            XrainError::UnexpectedHeaderKind { offset, found } => write!(
+                f,
+                "unexpected header kind 0x{:02X} at offset {}",
+                found, offset
+            ),
+            XrainError::BadTerminator { offset, found } => write!(
+                f,
+                "bad header terminator {:02X?} at offset {} (expected [00, 00])",
+                found, offset
+            ),
+            XrainError::InvalidDateTime { offset } => {
+                write!(f, "invalid observation datetime at offset {}", offset)
+            }
+            XrainError::InvalidMeshCode { offset } => {
+                write!(f, "invalid mesh code at offset {}", offset)
+            }
+            XrainError::Truncated {
+                section,
+                offset,
+                needed,
+            } => write!(
+                f,
+                "truncated {} at offset {}: {} more byte(s) needed",
+                section, offset, needed
+            ),
+            XrainError::EmptyBlock { offset } => {
+                write!(f, "block at offset {} contains no mesh", offset)
+            }
+            XrainError::BlockCountMismatch { declared, found } => write!(
+                f,
+                "header declares {} block(s) but {} were read",
+                declared, found
+            ),
+            XrainError::UnsupportedArchive => write!(f, "unsupported archive format"),
+            XrainError::UnknownOwner { found } => write!(
+                f,
+                "unsupported owner code {} (only Kanto is supported)",
+                found
+            ),
+            XrainError::RegionExtentMismatch {
+                region,
+                bottom_left,
+                top_right,
+            } => write!(
+                f,
+                "mesh extent {}-{} is outside the {} composite",
+                bottom_left, top_right, region
+            ),
+            XrainError::CellCountMismatch {
+                code,
+                expected,
+                found,
+            } => write!(
+                f,
+                "mesh {} has {} cell(s) but the header expects {}",
+                code, found, expected
+            ),
+            XrainError::InvalidCellCount { code, found } => write!(
+                f,
+                "mesh {} has {} cell(s), which matches no mesh size",
+                code, found
+            ),
+            XrainError::CellOutOfRange { code, index } => write!(
+                f,
+                "cell {} of mesh {} does not fit in 4-bit quality and 12-bit rain",
+                index, code
+            ),
+            XrainError::InvalidBlock { code } => {
+                write!(f, "mesh {} does not continue the block eastward", code)
+            }
+            XrainError::TooLarge => write!(f, "too many blocks or bytes for one XRAIN file"),
+            XrainError::MissingHeaderField { field } => {
+                write!(f, "header field {} is not set", field)
+            }
+            XrainError::MosaicTooLarge {
+                bottom_left,
+                top_right,
+            } => write!(
+                f,
+                "mosaic of meshes {}-{} exceeds the cell limit",
+                bottom_left, top_right
+            ),
+            XrainError::InvalidExtent {
+                bottom_left,
+                top_right,
+            } => write!(
+                f,
+                "bottom-left mesh {} is north or east of top-right mesh {}",
+                bottom_left, top_right
+            ),
+        }
+    }
+}
+
+impl std::error::Error for XrainError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XrainError::Io(e) => Some(e),
+            XrainError::Csv(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for XrainError {
+    fn from(value: std::io::Error) -> Self {
+        XrainError::Io(value)
+    }
+}
+
+impl From<csv::Error> for XrainError {
+    fn from(value: csv::Error) -> Self {
+        XrainError::Csv(value)
+    }
+}
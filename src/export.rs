@@ -0,0 +1,345 @@
+//! 1次メッシュごとのcsvファイルへの書き出し
+//!
+//! 格子形式は1次メッシュを(320, 320)の表にし、0行目が北端、0列目が西端。
+//! 縦持ち形式は1セル1行で2次メッシュコード、2次メッシュ内の行と列、中心の緯度経度と値を書く。
+//! 値はどちらも12bitの雨量データと4bitの品質管理情報のまま。
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    AffineTransform, PrimaryMesh, Writer, XrainCell, XrainDataset, XrainError, XrainHeader,
+};
+
+/// Which values to export.
+///
+/// 書き出す値
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvLayer {
+    /// 雨量データ
+    #[default]
+    Rain,
+    /// 品質管理情報
+    Quality,
+    /// 雨量データと品質管理情報。格子形式ではファイルを分ける。
+    Both,
+}
+
+impl CsvLayer {
+    /// ファイル名の`{layer}`に入る名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsvLayer::Rain => "rain",
+            CsvLayer::Quality => "quality",
+            CsvLayer::Both => "both",
+        }
+    }
+
+    fn has_rain(&self) -> bool {
+        matches!(self, CsvLayer::Rain | CsvLayer::Both)
+    }
+
+    fn has_quality(&self) -> bool {
+        matches!(self, CsvLayer::Quality | CsvLayer::Both)
+    }
+}
+
+/// How cells are laid out in the file.
+///
+/// 表の形
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvLayout {
+    /// 1次メッシュを1つの表にする。データのない2次メッシュは空欄。
+    #[default]
+    Grid,
+    /// 1セル1行で`mesh_code,row,col,lat,lon,rain,quality`を書く。データのない2次メッシュは書かない。
+    Long,
+}
+
+/// Options for [`save_as_csv`].
+///
+/// csvの書き出し設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    out_dir: PathBuf,
+    file_name: String,
+    layer: CsvLayer,
+    layout: CsvLayout,
+    coordinate_header: bool,
+}
+
+impl CsvOptions {
+    /// ファイル名の既定値
+    pub const DEFAULT_FILE_NAME: &'static str = "{primary}_{datetime}.csv";
+
+    /// `out_dir`に雨量データを格子形式で書き出す設定
+    pub fn new<P: AsRef<Path>>(out_dir: P) -> Self {
+        Self {
+            out_dir: out_dir.as_ref().to_path_buf(),
+            file_name: Self::DEFAULT_FILE_NAME.to_string(),
+            layer: CsvLayer::default(),
+            layout: CsvLayout::default(),
+            coordinate_header: false,
+        }
+    }
+
+    /// ファイル名のテンプレート。
+    /// `{primary}`は1次メッシュコード、`{datetime}`は観測日時(`20191011-0000`)、
+    /// `{region}`は地整(`KANTO`)、`{layer}`は`rain`、`quality`、`both`に置き換える。
+    pub fn file_name<S: Into<String>>(mut self, template: S) -> Self {
+        self.file_name = template.into();
+        self
+    }
+
+    pub fn layer(mut self, layer: CsvLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn layout(mut self, layout: CsvLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// 格子形式で、先頭行に各列の中心の経度、先頭列に各行の中心の緯度を書くか。
+    /// 縦持ち形式では列名の行を常に書く。
+    pub fn coordinate_header(mut self, enabled: bool) -> Self {
+        self.coordinate_header = enabled;
+        self
+    }
+
+    /// 1次メッシュのファイルのパス。
+    /// 格子形式で両方を書き出すときにテンプレートに`{layer}`がなければ、拡張子の前に`_rain`などを付ける。
+    pub fn path(&self, header: &XrainHeader, primary: &PrimaryMesh, layer: CsvLayer) -> PathBuf {
+        let t = header.datetime();
+        let region = header
+            .region()
+            .map_or_else(|| header.owner().to_string(), |r| r.name_en().to_string());
+        let mut template = self.file_name.clone();
+        if self.layer == CsvLayer::Both
+            && self.layout == CsvLayout::Grid
+            && !template.contains("{layer}")
+        {
+            match template.rfind('.') {
+                Some(dot) => template.insert_str(dot, "_{layer}"),
+                None => template.push_str("_{layer}"),
+            }
+        }
+        let name = template
+            .replace("{primary}", &primary.code().to_string())
+            .replace(
+                "{datetime}",
+                &format!(
+                    "{:04}{:02}{:02}-{:02}{:02}",
+                    t.year(),
+                    t.month(),
+                    t.day(),
+                    t.hour(),
+                    t.minute()
+                ),
+            )
+            .replace("{region}", &region)
+            .replace("{layer}", layer.name());
+        self.out_dir.join(name)
+    }
+}
+
+/// 1次メッシュごとにcsvファイルを書き出す。出力先のディレクトリがなければ作る。
+/// 書き出したファイルのパスを1次メッシュコード順に返す。
+pub fn save_as_csv(data: &XrainDataset, options: &CsvOptions) -> Result<Vec<PathBuf>, XrainError> {
+    fs::create_dir_all(&options.out_dir)?;
+    let mut paths = Vec::new();
+    for primary in data.primary_meshes() {
+        match options.layout {
+            CsvLayout::Grid => {
+                let layers: &[CsvLayer] = match options.layer {
+                    CsvLayer::Both => &[CsvLayer::Rain, CsvLayer::Quality],
+                    CsvLayer::Rain => &[CsvLayer::Rain],
+                    CsvLayer::Quality => &[CsvLayer::Quality],
+                };
+                for &layer in layers {
+                    let path = options.path(data.header(), primary, layer);
+                    write_grid(&path, primary, layer, options.coordinate_header)?;
+                    paths.push(path);
+                }
+            }
+            CsvLayout::Long => {
+                let path = options.path(data.header(), primary, options.layer);
+                write_long(&path, primary, options.layer)?;
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+impl XrainDataset {
+    /// 1次メッシュごとにcsvファイルを書き出す。`save_as_csv`を参照。
+    pub fn save_csv(&self, options: &CsvOptions) -> Result<Vec<PathBuf>, XrainError> {
+        save_as_csv(self, options)
+    }
+}
+
+fn layer_value(cell: &XrainCell, layer: CsvLayer) -> u16 {
+    match layer {
+        CsvLayer::Quality => cell.quality(),
+        _ => cell.strength(),
+    }
+}
+
+fn write_grid(
+    path: &Path,
+    primary: &PrimaryMesh,
+    layer: CsvLayer,
+    coordinate_header: bool,
+) -> Result<(), XrainError> {
+    let side = primary.cells_per_side();
+    let size = 8 * side;
+    let mut grid: Vec<Option<u16>> = vec![None; size * size];
+    for mesh in primary.secondary() {
+        let row0 = usize::from(7 - mesh.code().y()) * side;
+        let col0 = usize::from(mesh.code().x()) * side;
+        for (i, cell) in mesh.cells().iter().enumerate() {
+            grid[(row0 + i / side) * size + col0 + i % side] = Some(layer_value(cell, layer));
+        }
+    }
+
+    let transform = AffineTransform::covering(primary.code().bounds(), size, size);
+    let mut wtr = Writer::from_path(path)?;
+    if coordinate_header {
+        let mut line = vec![String::from("lat\\lon")];
+        line.extend((0..size).map(|col| transform.cell_bounds(0, col).center().1.to_string()));
+        wtr.write_record(&line)?;
+    }
+    for (row, values) in grid.chunks(size).enumerate() {
+        let mut line: Vec<String> = Vec::with_capacity(size + 1);
+        if coordinate_header {
+            line.push(transform.cell_bounds(row, 0).center().0.to_string());
+        }
+        line.extend(
+            values
+                .iter()
+                .map(|v| v.map_or_else(String::new, |v| v.to_string())),
+        );
+        wtr.write_record(&line)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn write_long(path: &Path, primary: &PrimaryMesh, layer: CsvLayer) -> Result<(), XrainError> {
+    let mut wtr = Writer::from_path(path)?;
+    let mut columns = vec!["mesh_code", "row", "col", "lat", "lon"];
+    if layer.has_rain() {
+        columns.push("rain");
+    }
+    if layer.has_quality() {
+        columns.push("quality");
+    }
+    wtr.write_record(&columns)?;
+
+    for mesh in primary.secondary() {
+        let side = mesh.cells_per_side();
+        let transform = AffineTransform::covering(mesh.code().bounds(), side, side);
+        for (i, cell) in mesh.cells().iter().enumerate() {
+            let (row, col) = (i / side, i % side);
+            let (lat, lon) = transform.cell_bounds(row, col).center();
+            let mut line = vec![
+                mesh.code().to_string(),
+                row.to_string(),
+                col.to_string(),
+                lat.to_string(),
+                lon.to_string(),
+            ];
+            if layer.has_rain() {
+                line.push(cell.strength().to_string());
+            }
+            if layer.has_quality() {
+                line.push(cell.quality().to_string());
+            }
+            wtr.write_record(&line)?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{code, Fixture, Pattern};
+
+    fn out_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xrain-csv-{}-{}", name, std::process::id()))
+    }
+
+    fn dataset() -> XrainDataset {
+        XrainDataset::from_bytes(&Fixture::default().blocks([("543877", 2)]).build()).unwrap()
+    }
+
+    #[test]
+    fn test_grid() -> Result<(), XrainError> {
+        let dir = out_dir("grid");
+        let paths = save_as_csv(
+            &dataset(),
+            &CsvOptions::new(&dir)
+                .layer(CsvLayer::Both)
+                .coordinate_header(true),
+        )?;
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("5438_20191011-0000_rain.csv"),
+                dir.join("5438_20191011-0000_quality.csv"),
+                dir.join("5439_20191011-0000_rain.csv"),
+                dir.join("5439_20191011-0000_quality.csv"),
+            ]
+        );
+        let rain = fs::read_to_string(&paths[0])?;
+        let quality = fs::read_to_string(&paths[1])?;
+        fs::remove_dir_all(&dir)?;
+
+        let lines: Vec<Vec<&str>> = rain.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(lines.len(), 321);
+        assert_eq!(lines[0].len(), 321);
+        assert_eq!(lines[0][0], "lat\\lon");
+        let (lat, lon) = code("543877").cell(0, 0).unwrap().center();
+        assert!((lines[0][281].parse::<f64>().unwrap() - lon).abs() < 1e-9);
+        assert!((lines[1][0].parse::<f64>().unwrap() - lat).abs() < 1e-9);
+        let cell = Pattern::Gradient.cell(code("543877"), 0);
+        assert_eq!(lines[1][281], cell.strength().to_string());
+        assert_eq!(lines[1][1], "");
+
+        let lines: Vec<Vec<&str>> = quality.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(lines[1][281], cell.quality().to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_long() -> Result<(), XrainError> {
+        let dir = out_dir("long");
+        let paths = save_as_csv(
+            &dataset(),
+            &CsvOptions::new(&dir)
+                .file_name("{region}_{primary}_{layer}.csv")
+                .layout(CsvLayout::Long)
+                .layer(CsvLayer::Both),
+        )?;
+        assert_eq!(paths[0], dir.join("KANTO_5438_both.csv"));
+        let text = fs::read_to_string(&paths[0])?;
+        fs::remove_dir_all(&dir)?;
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + 1600);
+        assert_eq!(lines[0], "mesh_code,row,col,lat,lon,rain,quality");
+        let fields: Vec<&str> = lines[42].split(',').collect();
+        assert_eq!(&fields[..3], &["543877", "1", "1"]);
+        let (lat, lon) = code("543877").cell(1, 1).unwrap().center();
+        assert!((fields[3].parse::<f64>().unwrap() - lat).abs() < 1e-9);
+        assert!((fields[4].parse::<f64>().unwrap() - lon).abs() < 1e-9);
+        let cell = Pattern::Gradient.cell(code("543877"), 41);
+        assert_eq!(fields[5], cell.strength().to_string());
+        assert_eq!(fields[6], cell.quality().to_string());
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! XRAINのファイル名
+//!
+//! `KANTO00001-20191011-0000-G000-EL000000`のように
+//! 地整(英字)+連番5桁、観測日(YYYYMMDD)、観測時刻(hhmm)、プロダクト、仰角の5つを`-`でつなぐ。
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{ObservationTime, Region, XrainHeader};
+
+/// Error returned when a file name is not an XRAIN file name.
+///
+/// XRAINのファイル名として不正。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFileNameError {
+    input: String,
+}
+
+impl fmt::Display for ParseFileNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid XRAIN file name: {:?}", self.input)
+    }
+}
+
+impl std::error::Error for ParseFileNameError {}
+
+/// Metadata encoded in an XRAIN file name.
+///
+/// ファイル名から読み取れる情報
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct XrainFileName {
+    region: Region,
+    sequence: u32,
+    datetime: ObservationTime,
+    product: String,
+    elevation: u32,
+}
+
+/// 数字だけからなる`len`桁の文字列を数値にする。
+fn number(s: &str, len: usize) -> Option<u32> {
+    if s.len() != len || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+impl XrainFileName {
+    /// 各要素から作成する。productは英大文字1文字と数字3桁(例:G000)、
+    /// sequenceは5桁、elevationは6桁に収まらない場合は`None`。
+    pub fn new(
+        region: Region,
+        sequence: u32,
+        datetime: ObservationTime,
+        product: &str,
+        elevation: u32,
+    ) -> Option<Self> {
+        let bytes = product.as_bytes();
+        let valid_product = bytes.len() == 4
+            && bytes[0].is_ascii_uppercase()
+            && bytes[1..].iter().all(|b| b.is_ascii_digit());
+        if !valid_product || sequence > 99_999 || elevation > 999_999 {
+            return None;
+        }
+        Some(Self {
+            region,
+            sequence,
+            datetime,
+            product: product.into(),
+            elevation,
+        })
+    }
+
+    /// パスのファイル名部分を読む。`.gz`などの拡張子は無視する。
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ParseFileNameError> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| ParseFileNameError {
+                input: path.to_string_lossy().into(),
+            })?;
+        name.split('.').next().unwrap_or(name).parse()
+    }
+
+    /// 地整
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// 連番
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// 観測日時(日本標準時)。秒は0。
+    pub fn datetime(&self) -> ObservationTime {
+        self.datetime
+    }
+
+    /// プロダクトのコード(例:G000)
+    pub fn product(&self) -> &str {
+        &self.product
+    }
+
+    /// 仰角のコード
+    pub fn elevation(&self) -> u32 {
+        self.elevation
+    }
+
+    /// ヘッダーの地整と観測日時(分まで)がファイル名と一致するか。
+    pub fn matches_header(&self, header: &XrainHeader) -> bool {
+        let time = header.datetime();
+        header.region() == Some(self.region)
+            && time.year() == self.datetime.year()
+            && time.month() == self.datetime.month()
+            && time.day() == self.datetime.day()
+            && time.hour() == self.datetime.hour()
+            && time.minute() == self.datetime.minute()
+    }
+}
+
+impl fmt::Display for XrainFileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let t = &self.datetime;
+        write!(
+            f,
+            "{}{:05}-{:04}{:02}{:02}-{:02}{:02}-{}-EL{:06}",
+            self.region.name_en(),
+            self.sequence,
+            t.year(),
+            t.month(),
+            t.day(),
+            t.hour(),
+            t.minute(),
+            self.product,
+            self.elevation
+        )
+    }
+}
+
+impl FromStr for XrainFileName {
+    type Err = ParseFileNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseFileNameError { input: s.into() };
+        let parts: Vec<&str> = s.split('-').collect();
+        let [head, date, time, product, elevation] = parts[..] else {
+            return Err(err());
+        };
+        let split = head.find(|c: char| c.is_ascii_digit()).ok_or_else(err)?;
+        let region = Region::from_name_en(&head[..split]).ok_or_else(err)?;
+        let sequence = number(&head[split..], 5).ok_or_else(err)?;
+
+        number(date, 8).ok_or_else(err)?;
+        number(time, 4).ok_or_else(err)?;
+        let datetime = ObservationTime::new(
+            date[..4].parse().map_err(|_| err())?,
+            date[4..6].parse().map_err(|_| err())?,
+            date[6..].parse().map_err(|_| err())?,
+            time[..2].parse().map_err(|_| err())?,
+            time[2..].parse().map_err(|_| err())?,
+            0,
+        )
+        .ok_or_else(err)?;
+
+        let elevation = elevation
+            .strip_prefix("EL")
+            .and_then(|e| number(e, 6))
+            .ok_or_else(err)?;
+        Self::new(region, sequence, datetime, product, elevation).ok_or_else(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let name: XrainFileName = "KANTO00001-20191011-0000-G000-EL000000".parse().unwrap();
+        assert_eq!(name.region(), Region::Kanto);
+        assert_eq!(name.sequence(), 1);
+        assert_eq!(
+            name.datetime(),
+            ObservationTime::new(2019, 10, 11, 0, 0, 0).unwrap()
+        );
+        assert_eq!(name.product(), "G000");
+        assert_eq!(name.elevation(), 0);
+        assert_eq!(name.to_string(), "KANTO00001-20191011-0000-G000-EL000000");
+        assert_eq!(
+            XrainFileName::from_path("data/KANTO00001-20191011-0000-G000-EL000000.gz").unwrap(),
+            name
+        );
+
+        for bad in [
+            "KANTO0001-20191011-0000-G000-EL000000",
+            "TOKYO00001-20191011-0000-G000-EL000000",
+            "KANTO00001-20191311-0000-G000-EL000000",
+            "KANTO00001-20191011-0000-g000-EL000000",
+            "KANTO00001-20191011-0000-G000-000000",
+            "KANTO00001-20191011-0000",
+        ] {
+            assert!(bad.parse::<XrainFileName>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_matches_header() {
+        let name: XrainFileName = "KANTO00001-20191011-0010-G000-EL000000".parse().unwrap();
+        let builder = XrainHeader::builder()
+            .region(Region::Kanto)
+            .datetime(ObservationTime::new(2019, 10, 11, 0, 10, 0).unwrap());
+        assert!(name.matches_header(&builder.build().unwrap()));
+        assert!(!name.matches_header(&builder.owner(0).build().unwrap()));
+    }
+}
@@ -0,0 +1,269 @@
+//! 地域メッシュと緯度経度の変換
+//!
+//! 地域メッシュはJGD2011の緯度経度で定義される。WGS84との差は1m程度なので区別しない。
+
+use crate::{PrimaryMeshCode, QuarterTertiaryMeshCode, SecondaryMeshCode};
+
+/// 1次メッシュの緯度方向の幅(度)
+pub(crate) const PRIMARY_LAT: f64 = 2.0 / 3.0;
+/// 1次メッシュの経度方向の幅(度)
+pub(crate) const PRIMARY_LON: f64 = 1.0;
+/// 1次メッシュの1辺のセル数(8*40)
+const CELLS_PER_PRIMARY: u32 = 320;
+
+/// Latitude/longitude bounding box of a mesh, in degrees.
+///
+/// メッシュの緯度経度範囲(度)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatLonBounds {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl LatLonBounds {
+    /// 中心の(緯度, 経度)
+    pub fn center(&self) -> (f64, f64) {
+        (
+            (self.south + self.north) / 2.0,
+            (self.west + self.east) / 2.0,
+        )
+    }
+
+    /// 点が範囲内か。南端と西端は含み、北端と東端は含まない。
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        self.south <= lat && lat < self.north && self.west <= lon && lon < self.east
+    }
+}
+
+/// Affine transform from grid (row, column) to latitude/longitude, in the GDAL order.
+///
+/// 配列の行と列から緯度経度への変換。0行目が北端、0列目が西端で、回転はない。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AffineTransform {
+    /// 0列目の西端の経度
+    pub west: f64,
+    /// 1列あたりの経度(度)
+    pub lon_step: f64,
+    /// 0行目の北端の緯度
+    pub north: f64,
+    /// 1行あたりの緯度(度)。南へ進むので負。
+    pub lat_step: f64,
+}
+
+impl AffineTransform {
+    /// 緯度経度の範囲を`rows`行`cols`列に等分する変換
+    pub fn covering(bounds: LatLonBounds, rows: usize, cols: usize) -> Self {
+        Self {
+            west: bounds.west,
+            lon_step: (bounds.east - bounds.west) / cols as f64,
+            north: bounds.north,
+            lat_step: (bounds.south - bounds.north) / rows as f64,
+        }
+    }
+
+    /// GDALのGeoTransformの6要素
+    pub fn to_gdal(&self) -> [f64; 6] {
+        [
+            self.west,
+            self.lon_step,
+            0.0,
+            self.north,
+            0.0,
+            self.lat_step,
+        ]
+    }
+
+    /// 行列の位置(小数も可)の(緯度, 経度)。整数ならセルの北西の角。
+    pub fn lat_lon(&self, row: f64, col: f64) -> (f64, f64) {
+        (
+            self.north + row * self.lat_step,
+            self.west + col * self.lon_step,
+        )
+    }
+
+    /// セルの緯度経度の範囲
+    pub fn cell_bounds(&self, row: usize, col: usize) -> LatLonBounds {
+        let (north, west) = self.lat_lon(row as f64, col as f64);
+        let (south, east) = self.lat_lon(row as f64 + 1.0, col as f64 + 1.0);
+        LatLonBounds {
+            south,
+            west,
+            north,
+            east,
+        }
+    }
+
+    /// 緯度経度を含むセルの(行, 列)。北西より外なら`None`。
+    pub fn row_col(&self, lat: f64, lon: f64) -> Option<(usize, usize)> {
+        let row = ((lat - self.north) / self.lat_step).floor();
+        let col = ((lon - self.west) / self.lon_step).floor();
+        if row < 0.0 || col < 0.0 || !row.is_finite() || !col.is_finite() {
+            return None;
+        }
+        Some((row as usize, col as usize))
+    }
+}
+
+/// 南西端からのセル数で表した位置。範囲外なら`None`。
+fn cell_position(lat: f64, lon: f64) -> Option<(u32, u32)> {
+    let south = (lat / PRIMARY_LAT * f64::from(CELLS_PER_PRIMARY)).floor();
+    let west = ((lon - 100.0) / PRIMARY_LON * f64::from(CELLS_PER_PRIMARY)).floor();
+    let limit = f64::from(100 * CELLS_PER_PRIMARY);
+    if !(0.0..limit).contains(&south) || !(0.0..limit).contains(&west) {
+        return None;
+    }
+    Some((south as u32, west as u32))
+}
+
+impl PrimaryMeshCode {
+    /// 緯度経度の範囲
+    pub fn bounds(&self) -> LatLonBounds {
+        let south = f64::from(self.lat()) * PRIMARY_LAT;
+        let west = f64::from(self.lon()) + 100.0;
+        LatLonBounds {
+            south,
+            west,
+            north: south + PRIMARY_LAT,
+            east: west + PRIMARY_LON,
+        }
+    }
+
+    /// 中心の(緯度, 経度)
+    pub fn center(&self) -> (f64, f64) {
+        self.bounds().center()
+    }
+
+    /// 緯度経度を含む1次メッシュ
+    pub fn from_lat_lon(lat: f64, lon: f64) -> Option<Self> {
+        QuarterTertiaryMeshCode::from_lat_lon(lat, lon).map(|c| c.secondary().primary())
+    }
+}
+
+impl SecondaryMeshCode {
+    /// 緯度経度の範囲
+    pub fn bounds(&self) -> LatLonBounds {
+        let primary = self.primary().bounds();
+        let south = primary.south + f64::from(self.y()) * PRIMARY_LAT / 8.0;
+        let west = primary.west + f64::from(self.x()) * PRIMARY_LON / 8.0;
+        LatLonBounds {
+            south,
+            west,
+            north: south + PRIMARY_LAT / 8.0,
+            east: west + PRIMARY_LON / 8.0,
+        }
+    }
+
+    /// 中心の(緯度, 経度)
+    pub fn center(&self) -> (f64, f64) {
+        self.bounds().center()
+    }
+
+    /// 緯度経度を含む2次メッシュ
+    pub fn from_lat_lon(lat: f64, lon: f64) -> Option<Self> {
+        QuarterTertiaryMeshCode::from_lat_lon(lat, lon).map(|c| c.secondary())
+    }
+}
+
+impl QuarterTertiaryMeshCode {
+    /// 緯度経度の範囲
+    pub fn bounds(&self) -> LatLonBounds {
+        let secondary = self.secondary().bounds();
+        let dlat = PRIMARY_LAT / f64::from(CELLS_PER_PRIMARY);
+        let dlon = PRIMARY_LON / f64::from(CELLS_PER_PRIMARY);
+        let south = secondary.south + f64::from(39 - self.row()) * dlat;
+        let west = secondary.west + f64::from(self.col()) * dlon;
+        LatLonBounds {
+            south,
+            west,
+            north: south + dlat,
+            east: west + dlon,
+        }
+    }
+
+    /// 中心の(緯度, 経度)
+    pub fn center(&self) -> (f64, f64) {
+        self.bounds().center()
+    }
+
+    /// 緯度経度を含むセル
+    pub fn from_lat_lon(lat: f64, lon: f64) -> Option<Self> {
+        let (south, west) = cell_position(lat, lon)?;
+        let primary = PrimaryMeshCode::new(
+            (south / CELLS_PER_PRIMARY) as u8,
+            (west / CELLS_PER_PRIMARY) as u8,
+        )?;
+        let south = south % CELLS_PER_PRIMARY;
+        let west = west % CELLS_PER_PRIMARY;
+        let secondary = primary.secondary((south / 40) as u8, (west / 40) as u8)?;
+        secondary.cell((39 - south % 40) as u8, (west % 40) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        let primary: PrimaryMeshCode = "5438".parse().unwrap();
+        let b = primary.bounds();
+        assert!((b.south - 36.0).abs() < 1e-9);
+        assert!((b.west - 138.0).abs() < 1e-9);
+        assert!((b.north - 36.0 - 2.0 / 3.0).abs() < 1e-9);
+
+        let secondary: SecondaryMeshCode = "543870".parse().unwrap();
+        let b = secondary.bounds();
+        assert!((b.south - (36.0 + 7.0 / 12.0)).abs() < 1e-9);
+        assert!((b.east - 138.125).abs() < 1e-9);
+
+        // 北西端のセル
+        let b = secondary.cell(0, 0).unwrap().bounds();
+        assert!((b.north - (36.0 + 8.0 / 12.0)).abs() < 1e-9);
+        assert!((b.west - 138.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_transform() {
+        let transform = AffineTransform {
+            west: 138.0,
+            lon_step: 1.0 / 320.0,
+            north: 36.0 + 2.0 / 3.0,
+            lat_step: -2.0 / 3.0 / 320.0,
+        };
+        let cell = "543870"
+            .parse::<SecondaryMeshCode>()
+            .unwrap()
+            .cell(0, 0)
+            .unwrap();
+        let b = transform.cell_bounds(0, 0);
+        let expected = cell.bounds();
+        assert!((b.south - expected.south).abs() < 1e-9);
+        assert!((b.east - expected.east).abs() < 1e-9);
+        let (lat, lon) = cell.center();
+        assert_eq!(transform.row_col(lat, lon), Some((0, 0)));
+        assert_eq!(transform.row_col(36.01, 138.99), Some((315, 316)));
+        assert_eq!(transform.row_col(36.7, 138.5), None);
+        assert_eq!(transform.to_gdal()[1], 1.0 / 320.0);
+    }
+
+    #[test]
+    fn test_from_lat_lon() {
+        // 東京駅
+        let code = QuarterTertiaryMeshCode::from_lat_lon(35.681236, 139.767125).unwrap();
+        assert_eq!(code.tertiary_code(), 53394611);
+        assert!(code.bounds().contains(35.681236, 139.767125));
+        for cell in "543870".parse::<SecondaryMeshCode>().unwrap().cells() {
+            let (lat, lon) = cell.center();
+            assert_eq!(QuarterTertiaryMeshCode::from_lat_lon(lat, lon), Some(cell));
+        }
+        assert_eq!(
+            SecondaryMeshCode::from_lat_lon(36.6, 138.05)
+                .unwrap()
+                .to_string(),
+            "543870"
+        );
+        assert!(PrimaryMeshCode::from_lat_lon(35.0, 99.0).is_none());
+    }
+}
@@ -0,0 +1,197 @@
+//! 2次メッシュコードからファイル内の位置への索引
+//!
+//! ブロックヘッダーだけを読み、セルは読み飛ばす。1つの2次メッシュは`セル数*2`byteの固定長なので、
+//! ブロックの先頭の位置と何番目かから2次メッシュの位置が決まる。
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::view::BLOCK_HEADER_SIZE;
+use crate::{
+    read_block_header, read_header, read_single_block, BTreeMap, SecondaryMesh, SecondaryMeshCode,
+    Section, XrainBlockHeader, XrainError, XrainHeader, HEADER_SIZE,
+};
+
+/// Byte offsets of every secondary mesh in one file.
+///
+/// ファイル1つ分の2次メッシュの位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeshIndex {
+    header: XrainHeader,
+    /// 2次メッシュコードとセルの先頭の位置(ファイルの先頭からのバイト数)
+    offsets: BTreeMap<SecondaryMeshCode, usize>,
+}
+
+impl MeshIndex {
+    pub(crate) fn new(header: XrainHeader) -> Self {
+        Self {
+            header,
+            offsets: BTreeMap::new(),
+        }
+    }
+
+    /// ファイルを開いて索引を作る。
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, XrainError> {
+        Self::build(&mut File::open(path)?)
+    }
+
+    /// `reader`の先頭から索引を作る。ブロックヘッダー以外は読み飛ばす。
+    /// 同じ2次メッシュが複数あれば後のものを使う。
+    pub fn build<R: Read + Seek>(reader: &mut R) -> Result<Self, XrainError> {
+        let len = reader.seek(SeekFrom::End(0))? as usize;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        reader.take(HEADER_SIZE as u64).read_to_end(&mut buf)?;
+        let (_, header) = read_header(&buf)?;
+        let mesh_size = header.mesh_kind().cells_per_mesh() * 2;
+
+        let mut index = Self::new(header);
+        let mut pos = HEADER_SIZE;
+        for i in 0..index.header.block_num() {
+            if pos >= len {
+                return Err(XrainError::BlockCountMismatch {
+                    declared: index.header.block_num(),
+                    found: i,
+                });
+            }
+            let mut buf = [0u8; BLOCK_HEADER_SIZE];
+            let available = (len - pos).min(BLOCK_HEADER_SIZE);
+            reader.read_exact(&mut buf[..available])?;
+            let (_, block) = read_block_header(&buf[..available]).map_err(|e| e.shift(pos))?;
+            if block.is_empty() {
+                return Err(XrainError::EmptyBlock { offset: pos });
+            }
+            let offset = pos + BLOCK_HEADER_SIZE;
+            let end = offset + usize::from(block.len()) * mesh_size;
+            if end > len {
+                return Err(XrainError::Truncated {
+                    section: Section::Cell,
+                    offset: len,
+                    needed: end - len,
+                });
+            }
+            index.insert_block(&block, offset, pos)?;
+            reader.seek(SeekFrom::Start(end as u64))?;
+            pos = end;
+        }
+        Ok(index)
+    }
+
+    /// メモリ上のファイルから索引を作る。
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, XrainError> {
+        Self::build(&mut std::io::Cursor::new(bytes))
+    }
+
+    /// ブロックの2次メッシュを追加する。
+    ///
+    /// * offset ブロックの先頭の2次メッシュの位置
+    /// * at エラーに使うブロックヘッダーの位置
+    pub(crate) fn insert_block(
+        &mut self,
+        block: &XrainBlockHeader,
+        offset: usize,
+        at: usize,
+    ) -> Result<(), XrainError> {
+        let mesh_size = self.mesh_size();
+        for (i, code) in block.meshes().enumerate() {
+            let code = code.ok_or(XrainError::InvalidMeshCode { offset: at })?;
+            self.offsets.insert(code, offset + i * mesh_size);
+        }
+        Ok(())
+    }
+
+    /// ファイルのヘッダー
+    pub fn header(&self) -> &XrainHeader {
+        &self.header
+    }
+
+    /// 2次メッシュのセルの先頭の位置
+    pub fn offset(&self, code: SecondaryMeshCode) -> Option<usize> {
+        self.offsets.get(&code).copied()
+    }
+
+    /// 2次メッシュを含んでいるか。
+    pub fn contains(&self, code: SecondaryMeshCode) -> bool {
+        self.offsets.contains_key(&code)
+    }
+
+    /// 2次メッシュコードと位置(コード順)
+    pub fn iter(&self) -> impl Iterator<Item = (SecondaryMeshCode, usize)> + '_ {
+        self.offsets.iter().map(|(code, offset)| (*code, *offset))
+    }
+
+    /// 2次メッシュの数
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// 2次メッシュ1つ分のバイト数
+    pub fn mesh_size(&self) -> usize {
+        self.header.mesh_kind().cells_per_mesh() * 2
+    }
+
+    /// 索引を作ったファイルから2次メッシュだけをシークして読む。含まれなければ`None`。
+    pub fn read_mesh<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        code: SecondaryMeshCode,
+    ) -> Result<Option<SecondaryMesh>, XrainError> {
+        let offset = match self.offset(code) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        reader.seek(SeekFrom::Start(offset as u64))?;
+        let mut buf = vec![0u8; self.mesh_size()];
+        reader.read_exact(&mut buf)?;
+        let (_, cells) =
+            read_single_block(&buf, self.header.mesh_kind()).map_err(|e| e.shift(offset))?;
+        Ok(Some(SecondaryMesh::from_cells(code, cells)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{code, Corruption, Fixture, Pattern};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_index() -> Result<(), XrainError> {
+        let fixture = Fixture::default().blocks([("543877", 2), ("533900", 1)]);
+        let data = fixture.build();
+
+        let index = MeshIndex::from_bytes(&data)?;
+        assert_eq!(index.len(), 3);
+        // 543877の東隣は543970
+        let codes: Vec<String> = index.iter().map(|(c, _)| c.to_string()).collect();
+        assert_eq!(codes, vec!["533900", "543877", "543970"]);
+        assert_eq!(index.offset("543970".parse().unwrap()), Some(64 + 4 + 3200));
+        assert_eq!(
+            index.offset("533900".parse().unwrap()),
+            Some(64 + 4 + 6400 + 4)
+        );
+
+        let mut reader = Cursor::new(&data);
+        let mesh = index
+            .read_mesh(&mut reader, "533900".parse().unwrap())?
+            .unwrap();
+        assert_eq!(
+            mesh.cell(39, 39),
+            Some(&Pattern::Gradient.cell(code("533900"), 1599))
+        );
+        assert!(index
+            .read_mesh(&mut reader, "533901".parse().unwrap())?
+            .is_none());
+
+        assert!(matches!(
+            MeshIndex::from_bytes(&fixture.corrupt(Corruption::Truncate(2)).build()),
+            Err(XrainError::Truncated { needed: 2, .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! データ種別
+//!
+//! データ種別1は0x80(XRAIN)のみ。データ種別2はプロダクト。
+//! データ種別3は実ファイル(関東)で0x0047(地整識別コードと同じ値)なので、メッシュの大きさとは限らない。
+//! 確認できているのは合成雨量の250mメッシュだけで、ファイルはどのプロダクト、データ種別3でも250mメッシュとして読む。
+
+use crate::mesh::CELLS_PER_SIDE;
+use crate::QuarterTertiaryMeshCode;
+
+/// Product stored in the file (data kind 2).
+///
+/// データ種別2
+///
+/// デコードには使わない。並びが違うプロダクトが確認できたらここで分ける。
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DataKind {
+    /// 合成レーダー雨量
+    #[default]
+    CompositeRain = 0x01,
+    /// 単一局レーダー雨量
+    SingleSiteRain = 0x02,
+}
+
+impl DataKind {
+    /// データ種別1の固定値
+    pub const KIND1: u8 = 0x80;
+
+    /// データ種別2から変換する。未対応なら`None`。
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            0x01 => Some(DataKind::CompositeRain),
+            0x02 => Some(DataKind::SingleSiteRain),
+            _ => None,
+        }
+    }
+
+    /// データ種別2の値
+    pub fn to_raw(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// Mesh size of the cells.
+///
+/// メッシュの大きさ
+/// 2次メッシュあたりのセル数で決まる。ファイルに書かれているのは`MeshKind::Quarter`だけで、
+/// 500m、1kmメッシュはファイル上の表し方が分からないので、メモリ上の2次メッシュにだけ使う。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MeshKind {
+    /// 1/4 3次メッシュ(約250m)。2次メッシュを40x40に分割する。
+    #[default]
+    Quarter,
+    /// 1/2 3次メッシュ(約500m)。2次メッシュを20x20に分割する。
+    Half,
+    /// 3次メッシュ(約1km)。2次メッシュを10x10に分割する。
+    Tertiary,
+}
+
+impl MeshKind {
+    /// 2次メッシュの1辺のセル数
+    pub fn cells_per_side(&self) -> usize {
+        match self {
+            MeshKind::Quarter => 40,
+            MeshKind::Half => 20,
+            MeshKind::Tertiary => 10,
+        }
+    }
+
+    /// 2次メッシュあたりのセル数
+    pub fn cells_per_mesh(&self) -> usize {
+        self.cells_per_side() * self.cells_per_side()
+    }
+
+    /// 1セルの1辺に並ぶ1/4 3次メッシュの数
+    pub(crate) fn scale(&self) -> usize {
+        usize::from(CELLS_PER_SIDE) / self.cells_per_side()
+    }
+
+    /// 1/4 3次メッシュを含むセルの、2次メッシュ内でのインデックス(北西から)
+    pub fn cell_index(&self, code: QuarterTertiaryMeshCode) -> usize {
+        let scale = self.scale();
+        usize::from(code.row()) / scale * self.cells_per_side() + usize::from(code.col()) / scale
+    }
+
+    /// 1/4 3次メッシュを含むセルの北西の角の1/4 3次メッシュ
+    pub fn cell_origin(&self, code: QuarterTertiaryMeshCode) -> QuarterTertiaryMeshCode {
+        let scale = self.scale() as u8;
+        QuarterTertiaryMeshCode::new(
+            code.secondary(),
+            code.row() - code.row() % scale,
+            code.col() - code.col() % scale,
+        )
+        .unwrap()
+    }
+
+    /// 2次メッシュあたりのセル数から求める。
+    pub fn from_cells_per_mesh(cells: usize) -> Option<Self> {
+        [MeshKind::Quarter, MeshKind::Half, MeshKind::Tertiary]
+            .into_iter()
+            .find(|k| k.cells_per_mesh() == cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw() {
+        assert_eq!(DataKind::from_raw(0x01), Some(DataKind::CompositeRain));
+        assert_eq!(DataKind::SingleSiteRain.to_raw(), 0x02);
+        assert_eq!(DataKind::from_raw(0x07), None);
+        for kind in [MeshKind::Quarter, MeshKind::Half, MeshKind::Tertiary] {
+            assert_eq!(
+                MeshKind::from_cells_per_mesh(kind.cells_per_mesh()),
+                Some(kind)
+            );
+        }
+        assert_eq!(MeshKind::Quarter.cells_per_mesh(), 1600);
+        assert_eq!(MeshKind::from_cells_per_mesh(1601), None);
+    }
+
+    #[test]
+    fn test_cell_index() {
+        let code = "543870".parse::<crate::SecondaryMeshCode>().unwrap();
+        let cell = code.cell(39, 5).unwrap();
+        assert_eq!(MeshKind::Quarter.cell_index(cell), 39 * 40 + 5);
+        assert_eq!(MeshKind::Half.cell_index(cell), 19 * 20 + 2);
+        assert_eq!(MeshKind::Tertiary.cell_index(cell), 9 * 10 + 1);
+        assert_eq!(
+            MeshKind::Tertiary.cell_origin(cell),
+            code.cell(36, 4).unwrap()
+        );
+        assert_eq!(MeshKind::Quarter.cell_origin(cell), cell);
+    }
+}
@@ -1,66 +1,194 @@
-use anyhow::{anyhow, Ok, Result};
+mod archive;
+mod catalog;
+mod dataset;
+mod datetime;
+mod error;
+mod export;
+mod filename;
+#[cfg(test)]
+mod fixture;
+mod geo;
+mod index;
+mod kind;
+mod mesh;
+mod mosaic;
+mod point;
+#[cfg(test)]
+mod proptests;
+mod quality;
+mod rain;
+mod region;
+mod status;
+mod stream;
+mod validate;
+mod view;
+mod writer;
+
+use anyhow::Result;
+pub use archive::{ArchiveEntries, ArchiveKind, XrainArchive};
+pub use catalog::{Cadence, Catalog, CatalogEntry, CatalogQuery, Gap};
+pub use chrono::{DateTime, FixedOffset, Utc};
 pub use csv::Writer;
-pub use ndarray::{concatenate, Array, Array2, Array3, ArrayView3, Axis};
-use nom::{
-    bytes, character,
-    error::{Error, ErrorKind},
-    Err, IResult, Needed, ToUsize,
-};
-use std::path::{Path, PathBuf};
-use std::{
-    any, array,
-    ffi::{c_char, c_ulonglong, CStr},
-    process::Output,
+pub use dataset::XrainDataset;
+pub use datetime::ObservationTime;
+pub use error::{Section, XrainError};
+pub use export::{save_as_csv, CsvLayer, CsvLayout, CsvOptions};
+pub use filename::{ParseFileNameError, XrainFileName};
+pub use geo::{AffineTransform, LatLonBounds};
+pub use index::MeshIndex;
+pub use kind::{DataKind, MeshKind};
+pub use mesh::{ParseMeshCodeError, PrimaryMeshCode, QuarterTertiaryMeshCode, SecondaryMeshCode};
+pub use mosaic::{Mosaic, MAX_MOSAIC_CELLS, NODATA_RAW};
+pub use ndarray::{concatenate, s, Array, Array2, Array3, ArrayView3, Axis};
+pub use nom::Needed;
+use nom::{bytes, Err, IResult, ToUsize};
+pub use point::{PointRain, Sampling};
+pub use quality::QualityFlag;
+pub use rain::{
+    RainValue, RAIN_MAX_RAW, RAIN_RESOLUTION, RAW_MISSING, RAW_NOT_OBSERVED, RAW_OUT_OF_RANGE,
 };
+pub use region::Region;
+pub use status::SystemStatus;
+use std::path::Path;
 pub use std::{collections::BTreeMap, io::Read};
+pub use stream::{StreamEvent, XrainStreamParser};
+pub use validate::{validate, ValidationIssue, ValidationReport};
+pub use view::{BlockEntry, MappedXrain, XrainView};
+pub use writer::{
+    write_block_header, write_header, write_single_block, XrainEncoder, XrainHeaderBuilder,
+};
 
 /// A header of XRAIN, which explains the number of blocks, data length(size), bottom left, upper right, etc...
 ///
 /// XRAINファイルのヘッダー
 /// 詳しくはドキュメントを参照されたい。
-/// かなり未実装。
-/// TODO:まじでいつか書く
-
+/// ファイルから読むか、`XrainHeader::builder`で作る。
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct XrainHeader {
     ///地整識別
     owner: u8,
+    ///データ種別2
+    data_kind: DataKind,
     ///データ種別3
-    /// 1byte:対象エリアの地整識別コード
-    mesh_kind: u16,
-    ///観測日時(WIP)
-    datetime: c_ulonglong,
+    /// 実ファイル(関東)では0x0047で、対象エリアの地整識別コードと同じ値。読んだ値のまま持つ。
+    data_kind3: u16,
+    ///観測日時(JST)
+    datetime: ObservationTime,
+    ///観測日時の予備9byte
+    datetime_reserved: [u8; 9],
+    ///システムステータス
+    system_status: SystemStatus,
+    ///装置No.
+    device_no: u8,
     ///応答ステータス
     response_status: u8,
     ///ブロック数
     block_num: u16,
-    ///ファイルのサイズ
+    ///データサイズ。ヘッダーを含むファイル全体のバイト数とみなしているが未確認。
     data_size: u32,
     ///南西端の1次メッシュコード
-    bottom_left_lat: u8,
-    bottom_left_lon: u8,
+    bottom_left: PrimaryMeshCode,
     ///北東端の1次メッシュコード
-    top_right_lat: u8,
-    top_right_lon: u8,
+    top_right: PrimaryMeshCode,
+    ///予備領域
+    reserved: [u8; 10],
 }
 
-///これいる？
-impl Default for XrainHeader {
-    fn default() -> Self {
+impl XrainHeader {
+    /// 読み込み前の空のヘッダー。観測日時と1次メッシュコードは読んだ値で埋める。
+    pub(crate) fn blank() -> Self {
         Self {
-            owner: 71,
-            mesh_kind: 0,
-            datetime: 0,
+            owner: 0,
+            data_kind: DataKind::CompositeRain,
+            data_kind3: 0,
+            datetime: ObservationTime::default(),
+            datetime_reserved: [0; 9],
+            system_status: SystemStatus::default(),
+            device_no: 0,
             response_status: 0,
             block_num: 0,
             data_size: 0,
-            bottom_left_lat: 0,
-            bottom_left_lon: 0,
-            top_right_lat: 0,
-            top_right_lon: 0,
+            bottom_left: PrimaryMeshCode::default(),
+            top_right: PrimaryMeshCode::default(),
+            reserved: [0; 10],
         }
     }
+
+    /// 地整識別
+    pub fn owner(&self) -> u8 {
+        self.owner
+    }
+
+    /// データ種別2(プロダクト)
+    pub fn data_kind(&self) -> DataKind {
+        self.data_kind
+    }
+
+    /// データ種別3の値
+    pub fn data_kind3(&self) -> u16 {
+        self.data_kind3
+    }
+
+    /// メッシュの大きさ。確認できているのは250mメッシュだけなので、データ種別3によらず`MeshKind::Quarter`。
+    pub fn mesh_kind(&self) -> MeshKind {
+        MeshKind::Quarter
+    }
+
+    /// 観測日時(日本標準時)
+    pub fn datetime(&self) -> ObservationTime {
+        self.datetime
+    }
+
+    /// 観測日時(協定世界時)
+    pub fn datetime_utc(&self) -> DateTime<Utc> {
+        self.datetime.to_utc()
+    }
+
+    /// システムステータス(16byteそのまま)
+    pub fn system_status(&self) -> &SystemStatus {
+        &self.system_status
+    }
+
+    /// 装置No.
+    pub fn device_no(&self) -> u8 {
+        self.device_no
+    }
+
+    /// 応答ステータス
+    pub fn response_status(&self) -> u8 {
+        self.response_status
+    }
+
+    /// ブロック数
+    pub fn block_num(&self) -> u16 {
+        self.block_num
+    }
+
+    /// ヘッダーに書かれたデータサイズ。ファイル全体のバイト数とみなしているが未確認。
+    pub fn data_size(&self) -> u32 {
+        self.data_size
+    }
+
+    /// 南西端の1次メッシュコード
+    pub fn bottom_left(&self) -> PrimaryMeshCode {
+        self.bottom_left
+    }
+
+    /// 北東端の1次メッシュコード
+    pub fn top_right(&self) -> PrimaryMeshCode {
+        self.top_right
+    }
+
+    /// 観測日時の16byteのうち使われていない9byte。書き出すときはこの値のまま書く。
+    pub fn datetime_reserved(&self) -> [u8; 9] {
+        self.datetime_reserved
+    }
+
+    /// 予備領域の10byte。書き出すときはこの値のまま書く。
+    pub fn reserved(&self) -> [u8; 10] {
+        self.reserved
+    }
 }
 
 /// A block header stores structure of block which consists of multiple cells.
@@ -69,82 +197,177 @@ impl Default for XrainHeader {
 /// XRAINファイル内のブロックヘッダー
 /// ブロック：連続するセルの集合
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct XrainBlockHeader {
-    /// 先頭ブロックの1次メッシュコード上2桁
-    lat: u8,
-    /// 先頭ブロックの1次メッシュコード下2桁
-    lon: u8,
-
-    /// 先頭ブロックの2次メッシュコード下1桁
-    first_x: u8,
-    /// 先頭ブロックの2次メッシュコード上1桁
-    first_y: u8,
+    /// 先頭ブロックの2次メッシュコード
+    first: SecondaryMeshCode,
     /// 連続するセルの個数。
     length: u8,
 }
 
 impl XrainBlockHeader {
+    pub fn new(first: SecondaryMeshCode, length: u8) -> Self {
+        Self { first, length }
+    }
+
+    /// 先頭の2次メッシュコード
+    pub fn first(&self) -> SecondaryMeshCode {
+        self.first
+    }
+
+    /// ブロック内の2次メッシュコード。先頭から東へ連続し、1次メッシュの境界をまたぐこともある。
+    pub fn meshes(&self) -> impl Iterator<Item = Option<SecondaryMeshCode>> {
+        let first = self.first;
+        (0..i32::from(self.length)).map(move |i| first.offset(0, i))
+    }
+
     ///長さを取得
-    fn len(&self) -> u8 {
+    pub fn len(&self) -> u8 {
         self.length
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
 }
 
 /// 1次メッシュコード単位でのデータ
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PrimaryMesh {
-    /// 1次メッシュコードの上2桁
-    lat: u8,
-    lon: u8,
-    secondary: Vec<SecondaryMesh>,
+    /// 1次メッシュコード
+    code: PrimaryMeshCode,
+    /// 2次メッシュコードをキーにする2分木
+    secondary: BTreeMap<SecondaryMeshCode, SecondaryMesh>,
+}
+
+impl PrimaryMesh {
+    /// 2次メッシュを含まない1次メッシュを作成する。
+    pub fn new(code: PrimaryMeshCode) -> Self {
+        Self {
+            code,
+            secondary: BTreeMap::new(),
+        }
+    }
+
+    /// 1次メッシュコード
+    pub fn code(&self) -> PrimaryMeshCode {
+        self.code
+    }
+
+    /// 2次メッシュを追加する。同じコードのメッシュがあれば置き換えて古い方を返す。
+    /// 別の1次メッシュに属するメッシュと、すでにある2次メッシュと大きさが異なるメッシュは追加せずにそのまま返す。
+    pub fn insert(&mut self, mesh: SecondaryMesh) -> Option<SecondaryMesh> {
+        let mismatched = !self.is_empty() && mesh.cells_per_side() != self.cells_per_side();
+        if mesh.code.primary() != self.code || mismatched {
+            return Some(mesh);
+        }
+        self.secondary.insert(mesh.code, mesh)
+    }
+
+    /// 2次メッシュコードで取得する。
+    pub fn get(&self, code: SecondaryMeshCode) -> Option<&SecondaryMesh> {
+        self.secondary.get(&code)
+    }
+
+    /// 2次メッシュを含んでいるか。
+    pub fn contains(&self, code: SecondaryMeshCode) -> bool {
+        self.secondary.contains_key(&code)
+    }
+
+    /// 含まれる2次メッシュ(コード順)
+    pub fn secondary(&self) -> impl Iterator<Item = &SecondaryMesh> {
+        self.secondary.values()
+    }
+
+    /// 含まれる2次メッシュの数
+    pub fn len(&self) -> usize {
+        self.secondary.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secondary.is_empty()
+    }
+
+    /// 64個の2次メッシュのうちデータがある割合
+    pub fn coverage(&self) -> f64 {
+        self.secondary.len() as f64 / 64.0
+    }
+
+    /// 2次メッシュの1辺のセル数。2次メッシュがなければ250mメッシュの40。
+    pub fn cells_per_side(&self) -> usize {
+        self.secondary
+            .values()
+            .next()
+            .map_or(MeshKind::Quarter.cells_per_side(), |m| m.cells_per_side())
+    }
+
+    /// 雨量と品質の(2, 320, 320)配列。250mメッシュ以外では8*`cells_per_side`四方。
+    /// 0行目が北端、0列目が西端。データのない2次メッシュは0で埋める。
+    pub fn to_ndarray(&self) -> Array3<u16> {
+        let side = self.cells_per_side();
+        let mut merged = Array3::<u16>::zeros((2, 8 * side, 8 * side));
+        for mesh in self.secondary.values() {
+            let row = usize::from(7 - mesh.code.y()) * side;
+            let col = usize::from(mesh.code.x()) * side;
+            merged
+                .slice_mut(s![.., row..row + side, col..col + side])
+                .assign(&Array3::<u16>::from(mesh));
+        }
+        merged
+    }
+
+    /// 雨量(mm/h)の(320, 320)配列。特殊値とデータのない2次メッシュはNaN。
+    pub fn rain_mm_ndarray(&self) -> Array2<f64> {
+        let side = self.cells_per_side();
+        let mut merged = Array2::<f64>::from_elem((8 * side, 8 * side), f64::NAN);
+        for mesh in self.secondary.values() {
+            let row = usize::from(7 - mesh.code.y()) * side;
+            let col = usize::from(mesh.code.x()) * side;
+            merged
+                .slice_mut(s![row..row + side, col..col + side])
+                .assign(&mesh.rain_mm_ndarray());
+        }
+        merged
+    }
 }
 
 /// Secondary mesh which contains rainfall.
 /// 2次メッシュ単位のデータ
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SecondaryMesh {
-    /// 1次メッシュの下２桁
-    primary_lon_code: u8,
-    /// 1次メッシュの上２桁
-    primary_lat_code: u8,
-    /// 緯度、経度に8分割された2次メッシュの番号
-    /// x:経度方向 増加方向　西から東
-    /// y:緯度方向 増加方向　南から北
-    secondary_lon_code: u8,
-    secondary_lat_code: u8,
+    /// 2次メッシュコード
+    code: SecondaryMeshCode,
     /// 雨量データのvec40*40
     ///
     /// assert_eq!(xrain_cells.len(),1600);
     xrain_cells: CellComposite,
 }
 
-trait XrainMesh {}
-impl XrainMesh for SecondaryMesh {}
-
 /// It returns 3-dimensional array.
 ///
 impl From<SecondaryMesh> for Array3<u16> {
     fn from(value: SecondaryMesh) -> Self {
+        let side = value.cells_per_side();
         let mut rain: Vec<u16> = value.xrain_cells.iter().map(|f| f.strength).collect();
         //let arr_rain = Array::from_shape_vec((40, 40), rain).unwrap();
         let mut quality: Vec<u16> = value.xrain_cells.iter().map(|f| f.quality).collect();
         //let arr_qual = Array::from_shape_vec((40, 40), quality).unwrap();
         rain.append(&mut quality);
-        Array::from_shape_vec((2, 40, 40), rain).unwrap()
+        Array::from_shape_vec((2, side, side), rain).unwrap()
     }
 }
 
 impl From<&SecondaryMesh> for Array3<u16> {
     fn from(value: &SecondaryMesh) -> Self {
+        let side = value.cells_per_side();
         let mut rain: Vec<u16> = value.xrain_cells.iter().map(|f| f.strength).collect();
         //let arr_rain = Array::from_shape_vec((40, 40), rain).unwrap();
         let mut quality: Vec<u16> = value.xrain_cells.iter().map(|f| f.quality).collect();
         //let arr_qual = Array::from_shape_vec((40, 40), quality).unwrap();
         rain.append(&mut quality);
-        Array::from_shape_vec((2, 40, 40), rain).unwrap()
+        Array::from_shape_vec((2, side, side), rain).unwrap()
     }
 }
 
@@ -154,68 +377,104 @@ impl From<&SecondaryMesh> for Array3<u16> {
 type CellComposite = Vec<XrainCell>;
 
 impl SecondaryMesh {
-    /// SecondaryMeshのインスタンスを作成t
-    /// primary_x:1次メッシュコードの下2桁
-    /// primary_y:1次メッシュコードの上2桁
-    /// x:2次メッシュコードの下1桁
-    /// y:2次メッシュコードの上1桁
-    /// TODO:順番が逆なのが気持ち悪いから修正。543870なら385407の順番で与える必要がある。気持ち悪すぎる。
-    fn new(primary_lat_code: u8, primary_lon_code: u8, y: u8, x: u8, cells: CellComposite) -> Self {
+    /// SecondaryMeshのインスタンスを作成
+    /// cells:北西から東へ、北から南へ並んだ40x40=1600個のセル。
+    /// 250mメッシュ以外では`MeshKind::cells_per_mesh`個。
+    /// セル数がどのメッシュの大きさとも一致しなければ`XrainError::InvalidCellCount`。
+    pub fn new(code: SecondaryMeshCode, cells: CellComposite) -> Result<Self, XrainError> {
+        if MeshKind::from_cells_per_mesh(cells.len()).is_none() {
+            return Err(XrainError::InvalidCellCount {
+                code,
+                found: cells.len(),
+            });
+        }
+        Ok(Self::from_cells(code, cells))
+    }
+
+    /// セル数を確かめずに作成する。パーサーが`MeshKind::cells_per_mesh`個読んだセルに使う。
+    pub(crate) fn from_cells(code: SecondaryMeshCode, cells: CellComposite) -> Self {
         Self {
-            primary_lat_code,
-            primary_lon_code,
-            secondary_lat_code: y,
-            secondary_lon_code: x,
+            code,
             xrain_cells: cells,
         }
     }
 
-    pub fn zeros(primary_lat_code: u8, primary_lon_code: u8, y: u8, x: u8) -> Self {
-        let mut xrain_cells: CellComposite = Vec::new();
-        xrain_cells.reserve(1600);
+    /// すべてのセルが0の2次メッシュ
+    pub fn zeros(code: SecondaryMeshCode) -> Self {
+        Self::from_cells(code, vec![XrainCell::default(); 1600])
+    }
 
-        for _i in 0..1600 {
-            xrain_cells.push(XrainCell {
-                quality: 0,
-                strength: 0,
-            });
+    /// 2次メッシュコード
+    pub fn code(&self) -> SecondaryMeshCode {
+        self.code
+    }
+
+    /// 1600個のセル
+    pub fn cells(&self) -> &[XrainCell] {
+        &self.xrain_cells
+    }
+
+    /// 1辺のセル数。250mメッシュなら40。
+    pub fn cells_per_side(&self) -> usize {
+        self.mesh_kind().cells_per_side()
+    }
+
+    /// セル数から求めたメッシュの大きさ
+    pub fn mesh_kind(&self) -> MeshKind {
+        MeshKind::from_cells_per_mesh(self.xrain_cells.len())
+            .expect("cell count is checked when the mesh is built")
+    }
+
+    /// `row`行`col`列のセル。0行目が北端、0列目が西端。
+    pub fn cell(&self, row: usize, col: usize) -> Option<&XrainCell> {
+        let side = self.cells_per_side();
+        if row >= side || col >= side {
+            return None;
         }
-        SecondaryMesh {
-            primary_lat_code,
-            primary_lon_code,
-            secondary_lat_code: y,
-            secondary_lon_code: x,
-            xrain_cells,
+        self.xrain_cells.get(row * side + col)
+    }
+
+    /// 1/4 3次メッシュコードを含むセル。500m、1kmメッシュではそれを含む粗いセル。
+    /// 別の2次メッシュのコードなら`None`。
+    pub fn cell_at(&self, code: QuarterTertiaryMeshCode) -> Option<&XrainCell> {
+        if code.secondary() != self.code {
+            return None;
         }
+        self.xrain_cells.get(self.mesh_kind().cell_index(code))
     }
 
-    fn rain_ndarray(&self) -> Result<Array2<u16>, ndarray::ShapeError> {
+    /// 雨量の40x40配列
+    pub fn rain_ndarray(&self) -> Result<Array2<u16>, ndarray::ShapeError> {
+        let side = self.cells_per_side();
         let rain_vec: Vec<u16> = self.xrain_cells.iter().map(|f| f.strength).collect();
-        Array::from_shape_vec((40, 40), rain_vec)
+        Array::from_shape_vec((side, side), rain_vec)
     }
 
-    fn quality_ndarray(&self) -> Result<Array2<u16>, ndarray::ShapeError> {
+    /// 品質の40x40配列
+    pub fn quality_ndarray(&self) -> Result<Array2<u16>, ndarray::ShapeError> {
+        let side = self.cells_per_side();
         let quality_vec: Vec<u16> = self.xrain_cells.iter().map(|f| f.quality).collect();
-        Array::from_shape_vec((40, 40), quality_vec)
+        Array::from_shape_vec((side, side), quality_vec)
     }
 
-    /// TODO:記述
-    fn assign_cells(&mut self, cell_composite: CellComposite) -> Result<()> {
-        self.xrain_cells = cell_composite;
-        Ok(())
+    /// 雨量(mm/h)の40x40配列。特殊値はNaN。
+    pub fn rain_mm_ndarray(&self) -> Array2<f64> {
+        let side = self.cells_per_side();
+        Array2::from_shape_fn((side, side), |(row, col)| {
+            self.xrain_cells[row * side + col].rain().to_f64()
+        })
     }
 
-    /// csvファイルに保存する
-    fn save_csv<P: AsRef<Path>>(&self, out_path: P) -> Result<()> {
+    /// 雨量の40x40をcsvファイルに保存する
+    pub fn save_csv<P: AsRef<Path>>(&self, out_path: P) -> Result<(), XrainError> {
         let mut wtr = Writer::from_path(out_path)?;
-        let xsize: usize = 40;
-        let ysize: usize = 40;
+        let xsize: usize = self.cells_per_side();
+        let ysize: usize = xsize;
 
         for i in 0..ysize {
-            let mut vline = Vec::<u16>::new();
-            vline.reserve(xsize);
+            let mut vline = Vec::<u16>::with_capacity(xsize);
             for j in 0..xsize {
-                let index = i * 40 + j;
+                let index = i * xsize + j;
                 vline.push(self.xrain_cells.get(index).unwrap().strength);
             }
             wtr.serialize(vline)?;
@@ -224,30 +483,10 @@ impl SecondaryMesh {
         Ok(())
     }
 
-    fn ndarray() -> Result<Array3<u16>> {
-        todo!()
-    }
-}
-
-fn save_ndarray<P: AsRef<Path>>(file_path: P, array: Array3<u16>) -> Result<()> {
-    let mut wtr = Writer::from_path(file_path)?;
-    //横の長さ
-    let xsize = array.shape()[2];
-    //縦の長さ
-    let ysize = array.shape()[1];
-
-    for i in 0..ysize {
-        let mut vline: Vec<u16> = Vec::new();
-        vline.reserve(xsize);
-
-        for j in 0..xsize {
-            let value = array.get((0, i, j)).unwrap();
-            vline.push(*value);
-        }
-        wtr.serialize(vline)?;
+    /// 雨量と品質の(2, 40, 40)配列。`Array3::from`と同じ。
+    pub fn ndarray(&self) -> Array3<u16> {
+        Array3::from(self)
     }
-    wtr.flush()?;
-    Ok(())
 }
 
 /// Has quality and rainfall data.(cf. XRAIN document)
@@ -255,7 +494,7 @@ fn save_ndarray<P: AsRef<Path>>(file_path: P, array: Array3<u16>) -> Result<()>
 /// 雨量データと品質データ
 ///
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct XrainCell {
     ///品質データ
     quality: u16,
@@ -263,6 +502,32 @@ pub struct XrainCell {
     strength: u16,
 }
 
+impl XrainCell {
+    pub fn new(quality: u16, strength: u16) -> Self {
+        Self { quality, strength }
+    }
+
+    ///品質データ(4bit)
+    pub fn quality(&self) -> u16 {
+        self.quality
+    }
+
+    ///雨量(12bit)
+    pub fn strength(&self) -> u16 {
+        self.strength
+    }
+
+    ///品質管理情報
+    pub fn quality_flag(&self) -> QualityFlag {
+        QualityFlag::from_raw(self.quality)
+    }
+
+    ///雨量(mm/h)と特殊値。分解能と特殊値のコードは暫定値。
+    pub fn rain(&self) -> RainValue {
+        RainValue::from_raw(self.strength)
+    }
+}
+
 fn take_streaming<C>(i: &[u8], c: C) -> IResult<&[u8], &[u8]>
 where
     C: ToUsize,
@@ -270,285 +535,270 @@ where
     bytes::streaming::take(c)(i)
 }
 
-fn take_complete(i: &[u8]) -> IResult<&[u8], &[u8]> {
-    bytes::complete::take(1u8)(i)
+/// `take_streaming`の`Incomplete`を`XrainError::Truncated`に変換する。
+///
+/// * offset `input`の先頭の位置
+fn take_section<C>(
+    input: &[u8],
+    c: C,
+    section: Section,
+    offset: usize,
+) -> Result<(&[u8], &[u8]), XrainError>
+where
+    C: ToUsize,
+{
+    take_streaming(input, c).map_err(|e| {
+        let needed = match e {
+            Err::Incomplete(Needed::Size(n)) => n.get(),
+            _ => 0,
+        };
+        XrainError::Truncated {
+            section,
+            offset,
+            needed,
+        }
+    })
 }
 
-fn load_file_as_slice<P: AsRef<Path>>(file_path: P) -> Result<Vec<u8>> {
-    let mut file = std::fs::File::open(file_path).expect("file open failed");
+fn load_file_as_slice<P: AsRef<Path>>(file_path: P) -> Result<Vec<u8>, XrainError> {
+    let mut file = std::fs::File::open(file_path)?;
     let mut buf: Vec<u8> = Vec::new();
-    file.read_to_end(&mut buf).expect("file read failed");
+    file.read_to_end(&mut buf)?;
     Ok(buf)
 }
 
 /// ファイルをパースする。
 ///
-/// Result<BTreeMap<PrimaryCode,BTreeMap<SecondaryCode,SecondaryMesh>>>
-///
 /// * file_path ファイル名
-pub fn open_xrain<P: AsRef<Path>>(
-    file_path: P,
-) -> Result<BTreeMap<PrimaryCode, BTreeMap<SecondaryCode, SecondaryMesh>>> {
+pub fn open_xrain<P: AsRef<Path>>(file_path: P) -> Result<XrainDataset, XrainError> {
     // Open file.
     //ファイルを開く。
     let xrain = load_file_as_slice(file_path)?;
+    read_xrain(xrain.as_slice())
+}
+
+/// `reader`を最後まで読んでパースする。
+pub fn read_xrain_from<R: Read>(mut reader: R) -> Result<XrainDataset, XrainError> {
+    let mut buf: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    read_xrain(buf.as_slice())
+}
+
+/// メモリ上のファイル1つ分をパースする。
+///
+/// エラーの`offset`は`xrain`の先頭からの位置。
+pub fn read_xrain(xrain: &[u8]) -> Result<XrainDataset, XrainError> {
     // Read header
     // ヘッダーを読む。
-    let (input, header) = read_header(xrain.as_slice())?;
+    let (input, header) = read_header(xrain)?;
     // inputをmutableに変更
     let mut buf = input;
 
-    //1次メッシュコードをキーにする2分木。
-    let mut primary_map: BTreeMap<usize, BTreeMap<usize, SecondaryMesh>> = BTreeMap::new();
+    let mut meshes: Vec<SecondaryMesh> = Vec::new();
+    let mut blocks: Vec<XrainBlockHeader> = Vec::new();
 
     for i in 0..header.block_num {
-        let (input_internal, meshes) = read_sequential_block(buf)?;
-        buf = input_internal;
-
-        if meshes.is_empty() {
-            return Err(anyhow::anyhow!(
-                "Mesh vector is empty! Some failure occured."
-            ));
-        }
-        //Start code
-        for v in meshes.into_iter() {
-            let lat_code = Into::<usize>::into(v.primary_lat_code);
-            let lon_code = Into::<usize>::into(v.primary_lon_code);
-
-            let p_code = lat_code * 100 + lon_code;
-
-            primary_map.entry(p_code).or_insert_with(BTreeMap::new);
-
-            if let Some(p_vec) = primary_map.get_mut(&p_code) {
-                let code = v.secondary_lat_code * 10 + v.secondary_lon_code;
-                let code = usize::from(code);
-                p_vec.insert(code, v);
-            } else {
-                return Err(anyhow::anyhow!("It has invalid value."));
-            }
+        if buf.is_empty() {
+            return Err(XrainError::BlockCountMismatch {
+                declared: header.block_num,
+                found: i,
+            });
         }
-
-        //End code
-
-        //-----Finalize-----
-        //以下触るな
-    }
-
-    let mut i: u16 = 0;
-    while i < header.block_num {
-        i += 1;
+        let (input_internal, block) = read_sequential_block(buf, header.mesh_kind())
+            .map_err(|e| e.shift(xrain.len() - buf.len()))?;
+        buf = input_internal;
+        blocks.push(XrainBlockHeader::new(block[0].code(), block.len() as u8));
+        meshes.extend(block);
     }
 
-    Ok(primary_map)
+    Ok(XrainDataset::new(header, meshes)?.with_blocks(blocks))
 }
-type PrimaryCode = usize;
-type SecondaryCode = usize;
-/// WIP
-/// TODO:実装
-pub fn save_as_csv(
-    data: BTreeMap<PrimaryCode, BTreeMap<SecondaryCode, SecondaryMesh>>,
-) -> Result<()> {
-    for (mesh_code, mesh) in data.into_iter() {
-        let mut mesh = mesh;
-        let mut merged_vec = Vec::<Array3<u16>>::new();
-        for i in 0..8 {
-            let mut view_vec: Vec<Array3<u16>> = Vec::new();
-            for j in 0..8 {
-                let code: usize = i * 10 + j;
-
-                mesh.entry(code)
-                    .or_insert_with(|| SecondaryMesh::zeros(0, 0, 0, 0));
-                let arr = mesh.get(&code).unwrap();
-                let arr = Array3::<u16>::from(arr);
-                view_vec.push(arr);
-            }
-
-            let arr_view: Vec<ArrayView3<u16>> = view_vec.iter().map(|f| f.view()).collect();
-
-            let merged = concatenate(Axis(2), arr_view.as_slice()).unwrap();
-            merged_vec.push(merged);
-        }
-
-        let mview: Vec<ArrayView3<u16>> = merged_vec.iter().map(|f| f.view()).rev().collect();
-        let merged_mesh = concatenate(Axis(1), mview.as_slice())?;
 
-        println!("{:?}", merged_mesh);
-        let mut out_path = PathBuf::new();
-
-        let mut wtr = Writer::from_path(out_path)?;
-
-        let xsize: usize = merged_mesh.shape()[2];
-        let ysize: usize = merged_mesh.shape()[1];
-
-        for i in 0..ysize {
-            let mut vline = Vec::<u16>::new();
-            vline.reserve(ysize);
-            for j in 0..xsize {
-                let value = merged_mesh.get((0, i, j)).unwrap();
-                vline.push(*value);
-            }
-            wtr.serialize(vline)?;
-        }
-        wtr.flush()?;
-    }
-    Ok(())
-}
+/// ヘッダーのバイト数
+pub const HEADER_SIZE: usize = 64;
 
 /// ヘッダーまで読み進めたスライスを返す（日本語正しいですか？)
-pub fn read_header(bin_slice: &[u8]) -> Result<(&[u8], XrainHeader)> {
-    let mut header = XrainHeader::default();
+///
+/// エラーの`offset`は`bin_slice`の先頭からの位置。
+pub fn read_header(bin_slice: &[u8]) -> Result<(&[u8], XrainHeader), XrainError> {
+    let mut header = XrainHeader::blank();
+    let offset = |rest: &[u8]| bin_slice.len() - rest.len();
 
     let input = bin_slice;
     //固定値チェック:1byte
-    println!("Checking 01");
-    let (input, extracted) = take_streaming(input, 1u8).unwrap();
-    assert_eq!(extracted, &[0xFD]);
+    let (input, extracted) = take_section(input, 1u8, Section::Header, offset(input))?;
+    if extracted != [0xFD] {
+        return Err(XrainError::BadMagic {
+            offset: 0,
+            found: extracted[0],
+        });
+    }
     //地整識別チェック:1byte
     //TODO:チェックをどうするか。
-    let (input, extracted) = take_streaming(input, 1u8).unwrap();
+    let (input, extracted) = take_section(input, 1u8, Section::Header, offset(input))?;
     header.owner = extracted[0];
 
-    println!("Checking 02");
     //データ種別1:1byte
-    let (input, extracted) = take_streaming(input, 1u8).unwrap();
-
-    assert_eq!(extracted, &[0x80]);
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 1u8, Section::Header, at)?;
+    if extracted != [DataKind::KIND1] {
+        return Err(XrainError::UnsupportedDataKind {
+            offset: at,
+            found: u16::from(extracted[0]),
+        });
+    }
     //データ種別2:1byte
-    //これが0x01じゃなかったら合成レーダー雨量ではない。(じゃあそのデータはなんなのかは検証しない)
-    let (input, extracted) = take_streaming(input, 1u8).unwrap();
-    assert_eq!(extracted, &[0x01]);
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 1u8, Section::Header, at)?;
+    header.data_kind = DataKind::from_raw(extracted[0]).ok_or(XrainError::UnsupportedDataKind {
+        offset: at,
+        found: u16::from(extracted[0]),
+    })?;
     //データ種別3:2byte
-    let (input, _extracted) = take_streaming(input, 2u8).unwrap();
+    //値の意味が確認できていないので検査しない。
+    let (input, extracted) = take_section(input, 2u8, Section::Header, offset(input))?;
+    header.data_kind3 = u16::from_be_bytes([extracted[0], extracted[1]]);
 
     //ヘッダ種別:1byte
-    let (input, extracted) = take_streaming(input, 1u8).unwrap();
-    assert_eq!(extracted, &[0x01]);
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 1u8, Section::Header, at)?;
+    if extracted != [0x01] {
+        return Err(XrainError::UnexpectedHeaderKind {
+            offset: at,
+            found: extracted[0],
+        });
+    }
     //観測値識別
-    let (input, extracted) = take_streaming(input, 1u8).unwrap();
-    assert_eq!(extracted, &[0x05]);
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 1u8, Section::Header, at)?;
+    if extracted != [0x05] {
+        return Err(XrainError::UnexpectedHeaderKind {
+            offset: at,
+            found: extracted[0],
+        });
+    }
     //観測日時
-    //TODO:Impl datetime
-    let (input, _extracted) = take_streaming(input, 16u8).unwrap();
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 16u8, Section::Header, at)?;
+    header.datetime =
+        ObservationTime::from_bcd(extracted).ok_or(XrainError::InvalidDateTime { offset: at })?;
+    header.datetime_reserved.copy_from_slice(&extracted[7..]);
 
     //システムステータス
-    let (input, _extracted) = take_streaming(input, 16u8).unwrap();
+    let (input, extracted) = take_section(input, 16u8, Section::Header, offset(input))?;
+    let mut bits = [0u8; 16];
+    bits.copy_from_slice(extracted);
+    header.system_status = SystemStatus::new(bits);
 
     //装置no.
-    let (input, _extracted) = take_streaming(input, 1u8).unwrap();
+    let (input, extracted) = take_section(input, 1u8, Section::Header, offset(input))?;
+    header.device_no = extracted[0];
 
     //11応答ステータス
-    let (input, extracted) = take_streaming(input, 1u8).unwrap();
+    let (input, extracted) = take_section(input, 1u8, Section::Header, offset(input))?;
     header.response_status = extracted[0];
 
     //ブロック数
-    println!("Checking block num");
-    let (input, extracted) = take_streaming(input, 2u8).unwrap();
-    let mut earr: [u8; 2] = [0; 2];
-    (0..2).for_each(|i| {
-        earr[i] = extracted[i];
-        println!("{}", extracted[i]);
-    });
-    let block_num = u16::from_be_bytes(earr);
-    println!("ブロック数 :{}", block_num);
-    header.block_num = block_num;
+    let (input, extracted) = take_section(input, 2u8, Section::Header, offset(input))?;
+    header.block_num = u16::from_be_bytes([extracted[0], extracted[1]]);
 
     //データサイズ
-    println!("Checking data size");
-
-    let (input, extracted) = take_streaming(input, 4u8).unwrap();
-    //TODO:earrじゃなくてもっとましな名前を付ける。
-    let mut earr: [u8; 4] = [0; 4];
-    (0..4).for_each(|i| {
-        earr[i] = extracted[i];
-        println!("{}", extracted[i]);
-    });
-    let datasize = u32::from_be_bytes(earr);
-    header.data_size = datasize;
-    println!("size :{}", datasize);
-    //南西端の１次メッシュコード bottom_left
-    let (input, extracted) = take_streaming(input, 2u8).unwrap();
-    let byte_upper_mask: u8 = 0b11110000;
-    let byte_lower_mask: u8 = 0b00001111;
-
-    let lat_upper = (extracted[0] & byte_upper_mask) >> 4;
-    let lat_lower = extracted[0] & byte_lower_mask;
+    let (input, extracted) = take_section(input, 4u8, Section::Header, offset(input))?;
+    header.data_size = u32::from_be_bytes([extracted[0], extracted[1], extracted[2], extracted[3]]);
 
-    header.bottom_left_lat = lat_upper * 10 + lat_lower;
-
-    let lon_upper = (extracted[1] & byte_upper_mask) >> 4;
-    let lon_lower = extracted[1] & byte_lower_mask;
+    //南西端の１次メッシュコード bottom_left
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 2u8, Section::Header, at)?;
+    header.bottom_left =
+        primary_from_bcd(extracted).ok_or(XrainError::InvalidMeshCode { offset: at })?;
 
-    header.bottom_left_lon = lon_upper * 10 + lon_lower;
+    //北東端の１次メッシュコード
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 2u8, Section::Header, at)?;
+    header.top_right =
+        primary_from_bcd(extracted).ok_or(XrainError::InvalidMeshCode { offset: at })?;
 
-    println!(
-        "South-west primary mesh code :{}{}",
-        header.bottom_left_lat, header.bottom_left_lon
-    );
+    //予備領域
+    let (input, extracted) = take_section(input, 10u8, Section::Header, offset(input))?;
+    header.reserved.copy_from_slice(extracted);
 
-    //北東端の１次メッシュコード
-    let (input, extracted) = take_streaming(input, 2u8).unwrap();
+    //固定値
+    let at = offset(input);
+    let (input, extracted) = take_section(input, 2u8, Section::Header, at)?;
+    if extracted != [0x00, 0x00] {
+        return Err(XrainError::BadTerminator {
+            offset: at,
+            found: [extracted[0], extracted[1]],
+        });
+    }
+    Ok((input, header))
+}
 
+/// 2桁のBCDを数値に変換する。どちらかの桁が9を超えれば`None`。
+fn from_bcd(byte: u8) -> Option<u8> {
     let byte_upper_mask: u8 = 0b11110000;
     let byte_lower_mask: u8 = 0b00001111;
-    let lat_upper = (extracted[0] & byte_upper_mask) >> 4;
-    let lat_lower = extracted[0] & byte_lower_mask;
-
-    header.top_right_lat = lat_upper * 10 + lat_lower;
-
-    let lon_upper = (extracted[1] & byte_upper_mask) >> 4;
-    let lon_lower = extracted[1] & byte_lower_mask;
-
-    header.top_right_lon = lon_upper * 10 + lon_lower;
-
-    println!(
-        "North-east primary mesh code :{}{}",
-        header.top_right_lat, header.top_right_lon
-    );
+    let upper = (byte & byte_upper_mask) >> 4;
+    let lower = byte & byte_lower_mask;
+    if upper > 9 || lower > 9 {
+        return None;
+    }
+    Some(upper * 10 + lower)
+}
 
-    //予備領域をスキップ
-    let (input, _extracted) = take_streaming(input, 10u8).unwrap();
+/// BCD2byteの1次メッシュコードを読む。
+fn primary_from_bcd(bytes: &[u8]) -> Option<PrimaryMeshCode> {
+    PrimaryMeshCode::new(from_bcd(bytes[0])?, from_bcd(bytes[1])?)
+}
 
-    //固定値
-    let (input, extracted) = take_streaming(input, 2u8).unwrap();
-    assert_eq!(extracted, &[0x00, 0x00]);
-    Ok((input, header))
+/// 0..100の数値を2桁のBCDに変換する。
+pub(crate) fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
 }
 
 /// ブロック内のすべてのセルを読む。
-pub fn read_sequential_block<'a>(input: &'a [u8]) -> Result<(&'a [u8], Vec<SecondaryMesh>)> {
+///
+/// * mesh_kind メッシュの大きさ(`XrainHeader::mesh_kind`)。2次メッシュあたりのセル数が決まる。
+///
+/// エラーの`offset`は`input`の先頭からの位置。
+pub fn read_sequential_block(
+    input: &[u8],
+    mesh_kind: MeshKind,
+) -> Result<(&[u8], Vec<SecondaryMesh>), XrainError> {
     let (input_buf, block_header) = read_block_header(input)?;
+    if block_header.is_empty() {
+        return Err(XrainError::EmptyBlock { offset: 0 });
+    }
     let mut buf = input_buf;
-    println!("{:?}", block_header);
     let mut v_smesh: Vec<SecondaryMesh> = Vec::new();
 
-    for i in 0..block_header.len() {
-        //先頭の2次メッシュコードに現在のセル番号を足して
-        //現在の1次メッシュコードと2次メッシュコードを計算。
-
-        //先頭の２次メッシュコードに処理しているブロックのインデックスを足す。
-        //それを８で割るとどこの１次メッシュに属しているかがわかる。
-        //TODO:u8で足りるよね？考える
-        let currentx = block_header.first_x + i;
-        let currenty = block_header.first_y;
-        let primary_x = block_header.lon + (currentx / 8);
-        let primary_y = block_header.lat;
-        let currentx = currentx % 8;
-        let (input_internal, cmp) = read_single_block(buf)?;
+    for code in block_header.meshes() {
+        //先頭の２次メッシュコードから東に数えて、1次メッシュの境界をまたいだら隣の1次メッシュに移る。
+        let code = code.ok_or(XrainError::InvalidMeshCode { offset: 0 })?;
+        let (input_internal, cmp) =
+            read_single_block(buf, mesh_kind).map_err(|e| e.shift(input.len() - buf.len()))?;
         buf = input_internal;
-        let smesh = SecondaryMesh::new(primary_y, primary_x, currenty, currentx, cmp);
+        let smesh = SecondaryMesh::from_cells(code, cmp);
         v_smesh.push(smesh);
     }
 
     Ok((buf, v_smesh))
 }
 
-/// ブロックの中のセルを一つ読む。
-pub fn read_single_block(input: &[u8]) -> Result<(&[u8], CellComposite)> {
-    let mut cellcmp = CellComposite::new();
+/// ブロックの中の2次メッシュを一つ読む。
+///
+/// エラーの`offset`は`input`の先頭からの位置。
+pub fn read_single_block(
+    input: &[u8],
+    mesh_kind: MeshKind,
+) -> Result<(&[u8], CellComposite), XrainError> {
+    let cells = mesh_kind.cells_per_mesh();
+    let mut cellcmp = CellComposite::with_capacity(cells);
     let mut buf = input;
-    //一つのセルに入っているデータ数は40x40=1600
-    for _i in 0..1600 {
-        let (input_internal, new_cell) = read_cell(buf)?;
+    //250mメッシュなら一つの2次メッシュに入っているデータ数は40x40=1600
+    for _i in 0..cells {
+        let (input_internal, new_cell) =
+            read_cell(buf).map_err(|e| e.shift(input.len() - buf.len()))?;
         buf = input_internal;
         cellcmp.push(new_cell);
     }
@@ -557,45 +807,32 @@ pub fn read_single_block(input: &[u8]) -> Result<(&[u8], CellComposite)> {
 
 /// 最小単位を読む。
 /// FIXME:ブロックの中に含まれるものもセルと言うが、勝手にセルを東西南北に40分割したデータもセルと言っているまじでよくない。修正すべき。(DONE)
-/// TODO:雨量データの観測範囲外とエラーデータの処理を書く。
-pub fn read_cell(input: &[u8]) -> Result<(&[u8], XrainCell)> {
+/// 雨量の物理量と特殊値は`XrainCell::rain`で得る。
+pub fn read_cell(input: &[u8]) -> Result<(&[u8], XrainCell), XrainError> {
     //品質管理情報マスク
     let quality_mask: u16 = 0b1111000000000000;
     //雨量データマスク
     let rain_mask: u16 = 0b0000111111111111;
-    let (out, extracted) = take_streaming(input, 2u8).unwrap();
-    let mut cell_array: [u8; 2] = [0; 2];
-    (0..2).for_each(|i| {
-        cell_array[i] = extracted[i];
-    });
-    let val = u16::from_be_bytes(cell_array);
+    let (out, extracted) = take_section(input, 2u8, Section::Cell, 0)?;
+    let val = u16::from_be_bytes([extracted[0], extracted[1]]);
     let strength = val & rain_mask;
     let quality = (val & quality_mask) >> 12;
     let raincell = XrainCell { quality, strength };
     Ok((out, raincell))
 }
 
-fn merge_secondary_mesh_by_row<U, V>() -> Result<()> {
-    todo!()
-}
-
 /// ブロックヘッダーを読む
-pub fn read_block_header(input: &[u8]) -> Result<(&[u8], XrainBlockHeader)> {
-    //緯度
-    let (input, lat) = take_streaming(input, 1u8).unwrap();
-    //経度
-    let (input, lon) = take_streaming(input, 1u8).unwrap();
-
-    //let prim_mesh_code = Into::<u32>::into(lat[0]) * 100 + Into::<u32>::into(lon[0]);
+///
+/// エラーの`offset`は`input`の先頭からの位置。
+pub fn read_block_header(input: &[u8]) -> Result<(&[u8], XrainBlockHeader), XrainError> {
+    let (input, extracted) = take_section(input, 4u8, Section::BlockHeader, 0)?;
 
     //１次メッシュコード上２桁
-    let lat = lat[0];
+    let lat = extracted[0];
     //１次メッシュコード下２桁
-    let lon = lon[0];
+    let lon = extracted[1];
 
-    let (input, mesh_code) = take_streaming(input, 1u8).unwrap();
-
-    let grid_position: u8 = mesh_code[0];
+    let grid_position: u8 = extracted[2];
     let ymask: u8 = 0b11110000;
     let xmask: u8 = 0b00001111;
 
@@ -605,224 +842,415 @@ pub fn read_block_header(input: &[u8]) -> Result<(&[u8], XrainBlockHeader)> {
     //１次メッシュ内での緯度位置(南から北,)
     let ynum = (grid_position & ymask) >> 4;
 
-    //連続するブロック数
-    let (input, block_num) = take_streaming(input, 1u8).unwrap();
+    let first = PrimaryMeshCode::new(lat, lon)
+        .and_then(|primary| primary.secondary(ynum, xnum))
+        .ok_or(XrainError::InvalidMeshCode { offset: 0 })?;
 
-    let block_header = XrainBlockHeader {
-        lat,
-        lon,
-        first_x: xnum,
-        first_y: ynum,
-        length: block_num[0],
-    };
+    //連続するブロック数
+    let block_header = XrainBlockHeader::new(first, extracted[3]);
 
     Ok((input, block_header))
 }
 
-/// XRAIN dataset
-/// It contains header and data.
-/// In future, it can be handled with gdal.
-#[repr(C)]
-pub struct CXrainDataset {
-    ///TODO:CXrainDatasetをmem::forgetした後、
-    /// XRAINheaderはどうなるんだろう。Dropされるのかな？
-    header: XrainHeader,
-
-    ///配列のポインタ。
-    ptr: *mut XrainCell,
-    ///The number of XrainCell.
-    length: u64,
-}
-
-#[repr(C)]
-pub struct CXrainResult {
-    status: bool,
-    data: CXrainDataset,
-}
+#[cfg(test)]
+mod tests {
 
-fn open_internal<P: AsRef<Path>>(file_path: P) -> Result<CXrainDataset> {
-    let xrain = load_file_as_slice(file_path)?;
-    let (input, header) = read_header(xrain.as_slice())?;
+    use std::str::FromStr;
 
-    let mut buf = input;
+    use ndarray::concatenate;
 
-    for _i in 0..header.block_num {
-        let (input_internal, meshes) = read_sequential_block(buf)?;
-        buf = input_internal;
-        if meshes.is_empty() {
-            return Err(anyhow::anyhow!(
-                "Mesh vector is empty! Some failure occured."
-            ));
-        }
-        //Start code
+    use super::*;
+    use crate::fixture::{code, Corruption, Fixture, Pattern};
 
-        //End code
+    #[test]
+    fn test_secondary_mesh_accessors() -> Result<()> {
+        let mut cells = vec![XrainCell::default(); 1600];
+        cells[41] = XrainCell::new(1, 250);
+        let code: SecondaryMeshCode = "543870".parse().unwrap();
+        let mesh = SecondaryMesh::new(code, cells)?;
+        assert_eq!(mesh.code(), code);
+        assert_eq!(mesh.cell(1, 1), Some(&XrainCell::new(1, 250)));
+        assert_eq!(
+            mesh.cell_at(code.cell(1, 1).unwrap()),
+            Some(&XrainCell::new(1, 250))
+        );
+        assert_eq!(mesh.cell(40, 0), None);
+        assert_eq!(mesh.rain_ndarray()?[[1, 1]], 250);
+        assert_eq!(mesh.quality_ndarray()?[[1, 1]], 1);
+
+        assert!(matches!(
+            SecondaryMesh::new(code, vec![XrainCell::default(); 5]),
+            Err(XrainError::InvalidCellCount { found: 5, .. })
+        ));
+        // 大きさの異なる2次メッシュは同じ1次メッシュに入らない。
+        let mut primary = PrimaryMesh::new(code.primary());
+        assert!(primary.insert(mesh).is_none());
+        let half = SecondaryMesh::new("543871".parse()?, vec![XrainCell::default(); 400])?;
+        assert!(primary.insert(half).is_some());
+        assert_eq!(primary.to_ndarray().shape(), &[2, 320, 320]);
+        Ok(())
     }
 
-    todo!()
-}
-
-/// WIP
-/// Open and get XRAIN dataset.
-/// TODO:実装
-#[no_mangle]
-pub extern "C" fn open_ffi(file_path: *const c_char) -> Option<CXrainResult> {
-    todo!()
-}
-
-#[cfg(test)]
-mod tests {
+    #[test]
+    fn test_block_crosses_primary() -> Result<()> {
+        let mut data = vec![54, 38, 0x07, 2];
+        data.extend_from_slice(&[0; 2 * 3200]);
+        let (input, meshes) = read_sequential_block(data.as_slice(), MeshKind::Quarter)?;
+        assert!(input.is_empty());
+        let codes: Vec<String> = meshes.iter().map(|m| m.code().to_string()).collect();
+        assert_eq!(codes, vec!["543807", "543900"]);
+
+        let data = [54, 38, 0x08, 1];
+        assert!(matches!(
+            read_block_header(&data),
+            Err(XrainError::InvalidMeshCode { offset: 0 })
+        ));
+        Ok(())
+    }
 
-    use std::str::FromStr;
+    #[test]
+    fn test_mesh_kind() -> Result<()> {
+        // 500mメッシュは2次メッシュあたり20x20セル
+        let code = code("543870");
+        let mesh = SecondaryMesh::new(
+            code,
+            (0..400).map(|i| Pattern::Gradient.cell(code, i)).collect(),
+        )?;
+        assert_eq!(mesh.mesh_kind(), MeshKind::Half);
+        assert_eq!(mesh.cell(1, 1), Some(&Pattern::Gradient.cell(code, 21)));
+        // 1/4 3次メッシュ(2, 3)は500mのセル(1, 1)に含まれる
+        assert_eq!(
+            mesh.cell_at(code.cell(2, 3).unwrap()),
+            Some(&Pattern::Gradient.cell(code, 21))
+        );
+        assert_eq!(
+            mesh.cell_at(code.cell(4, 3).unwrap()),
+            Some(&Pattern::Gradient.cell(code, 41))
+        );
+
+        let mut primary = PrimaryMesh::new(code.primary());
+        assert_eq!(primary.insert(mesh), None);
+        assert_eq!(primary.to_ndarray().shape(), &[2, 160, 160]);
+        assert_eq!(primary.quality_mask(|_| true).shape(), &[160, 160]);
+        Ok(())
+    }
 
-    use ndarray::concatenate;
+    #[test]
+    fn test_data_kind3_area_code() -> Result<()> {
+        // 関東の実ファイルのデータ種別3は地整識別コードと同じ0x0047
+        let data = Fixture::default().build();
+        assert_eq!(data[4..6], [0x00, 0x47]);
+        let dataset = read_xrain(&data)?;
+        assert_eq!(dataset.header().data_kind3(), 0x0047);
+        assert_eq!(dataset.header().mesh_kind(), MeshKind::Quarter);
+        assert_eq!(dataset.to_bytes()?, data);
+
+        // データ種別3やプロダクトが違っても250mメッシュとして読む
+        for data_kind3 in [0x0001, 0x0002] {
+            let data = Fixture::default()
+                .data_kind(DataKind::SingleSiteRain)
+                .data_kind3(data_kind3)
+                .blocks([("543870", 1)])
+                .build();
+            let dataset = read_xrain(&data)?;
+            assert_eq!(dataset.header().data_kind(), DataKind::SingleSiteRain);
+            assert_eq!(dataset.header().data_kind3(), data_kind3);
+            let mesh = dataset.secondary(code("543870")).unwrap();
+            assert_eq!(mesh.mesh_kind(), MeshKind::Quarter);
+            assert_eq!(dataset.to_bytes()?, data);
+        }
+        Ok(())
+    }
 
-    use super::*;
+    #[test]
+    fn test_truncated_block() {
+        let mut data = vec![54, 38, 0x00, 1];
+        data.extend_from_slice(&[0; 101]);
+        assert!(matches!(
+            read_sequential_block(data.as_slice(), MeshKind::Quarter),
+            Err(XrainError::Truncated {
+                section: Section::Cell,
+                offset: 104,
+                needed: 1
+            })
+        ));
+        assert!(matches!(
+            read_block_header(&data[..3]),
+            Err(XrainError::Truncated {
+                section: Section::BlockHeader,
+                offset: 0,
+                needed: 1
+            })
+        ));
+    }
 
     #[test]
     fn test_header_read() -> Result<()> {
-        let data = load_file_as_slice("KANTO00001-20191011-0000-G000-EL000000")?;
+        let data = Fixture::default().build();
         let (input, header) = read_header(data.as_slice())?;
-        assert_eq!(header.bottom_left_lat, 46);
-        assert_eq!(header.bottom_left_lon, 34);
-        assert_eq!(header.top_right_lat, 55);
-        assert_eq!(header.top_right_lon, 43);
+        assert_eq!(input.len(), data.len() - HEADER_SIZE);
+        assert_eq!(header.owner(), 71);
+        assert_eq!(header.bottom_left(), PrimaryMeshCode::new(46, 34).unwrap());
+        assert_eq!(header.top_right(), PrimaryMeshCode::new(55, 43).unwrap());
+        assert_eq!(
+            header.datetime(),
+            ObservationTime::new(2019, 10, 11, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            header.datetime_utc().to_rfc3339(),
+            "2019-10-10T15:00:00+00:00"
+        );
+        assert_eq!(header.block_num(), 4);
+        assert_eq!(header.data_size() as usize, data.len());
+        assert_eq!(
+            header.system_status().set_bits().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+
+        let data = Fixture::default()
+            .owner(70)
+            .extent("5638", "6142")
+            .datetime(ObservationTime::new(2020, 2, 29, 23, 55, 0).unwrap())
+            .data_kind3(70)
+            .build();
+        let (_, header) = read_header(data.as_slice())?;
+        assert_eq!(header.owner(), 70);
+        assert_eq!(header.bottom_left(), PrimaryMeshCode::new(56, 38).unwrap());
+        assert_eq!(header.region(), None);
+        assert_eq!(header.data_kind3(), 70);
+        assert_eq!(
+            header.datetime(),
+            ObservationTime::new(2020, 2, 29, 23, 55, 0).unwrap()
+        );
         Ok(())
     }
 
+    #[test]
+    fn test_header_corrupt() {
+        let read = |corruption| read_header(&Fixture::default().corrupt(corruption).build()).err();
+        assert!(matches!(
+            read(Corruption::BadMagic),
+            Some(XrainError::BadMagic { offset: 0, .. })
+        ));
+        assert!(matches!(
+            read(Corruption::BadTerminator),
+            Some(XrainError::BadTerminator {
+                offset: 62,
+                found: [0xFF, 0xFF]
+            })
+        ));
+        assert!(matches!(
+            read(Corruption::BadDateTime),
+            Some(XrainError::InvalidDateTime { offset: 8 })
+        ));
+        assert!(matches!(
+            read(Corruption::BadDataKind),
+            Some(XrainError::UnsupportedDataKind {
+                offset: 3,
+                found: 0x07
+            })
+        ));
+        // 1次メッシュコードの桁が9を超える
+        assert!(matches!(
+            read(Corruption::BadMeshCode),
+            Some(XrainError::InvalidMeshCode { offset: 48 })
+        ));
+        assert!(matches!(
+            read_header(&Fixture::default().build()[..40]),
+            Err(XrainError::Truncated {
+                section: Section::Header,
+                offset: 40,
+                needed: 1
+            })
+        ));
+    }
+
     #[test]
     fn test_read_single_block() -> Result<()> {
-        let data = load_file_as_slice("KANTO00001-20191011-0000-G000-EL000000")?;
+        let fixture = Fixture::default();
+        let data = fixture.build();
         let (input, header) = read_header(data.as_slice())?;
 
         let mut buf = input;
-
+        let mut read = Vec::new();
         for _i in 0..header.block_num {
-            let (input_internal, meshes) = read_sequential_block(buf)?;
+            let (input_internal, meshes) = read_sequential_block(buf, header.mesh_kind())?;
             buf = input_internal;
-            let mut tmeshes: Vec<SecondaryMesh> = meshes
-                .into_iter()
-                .filter(|f| f.primary_lat_code == 54 && f.primary_lon_code == 38)
-                .collect();
-            if tmeshes.is_empty() {
-            } else {
-                tmeshes.sort_by(|lhs, rhs| {
-                    return lhs.secondary_lon_code.cmp(&rhs.secondary_lon_code);
-                });
-
-                for v in tmeshes.into_iter() {
-                    println!(
-                        "{}{}{}{}",
-                        v.primary_lat_code,
-                        v.primary_lon_code,
-                        v.secondary_lat_code,
-                        v.secondary_lon_code
-                    );
-                    let name = v.primary_lat_code.to_string();
-                    let name = name
-                        + v.primary_lon_code.to_string().as_str()
-                        + v.secondary_lat_code.to_string().as_str()
-                        + v.secondary_lon_code.to_string().as_str()
-                        + ".csv";
-
-                    let mut out_path = PathBuf::from("data");
-                    out_path.push(name);
-
-                    v.save_csv(out_path)?;
-                }
-            }
-
-            // for v in meshes.iter() {
-            //     let file_name = v.primary_y.to_string() + v.primary_x.to_string().as_str();
-
-            //     let file_name =
-            //         file_name + v.y.to_string().as_str() + v.x.to_string().as_str() + ".csv";
-
-            //     let mut file_path = PathBuf::from("out");
-            //     file_path.push(file_name);
-
-            //     v.save_csv(file_path)?;
-            // }
+            read.extend(meshes);
         }
+        assert!(buf.is_empty());
+        assert_eq!(read, fixture.meshes());
+
+        // 1次メッシュ5438の南端の行は東隣の5439の2つとともに1つのブロックに入っている。
+        let mut tmeshes: Vec<SecondaryMesh> = read
+            .into_iter()
+            .filter(|f| f.code.primary() == PrimaryMeshCode::new(54, 38).unwrap())
+            .collect();
+        assert_eq!(tmeshes.len(), 8 + 8 + 8);
+        tmeshes.sort_by_key(|v| v.code.x());
+
+        let v = &tmeshes[0];
+        let (_, cells) = read_single_block(&data[HEADER_SIZE + 4 + 3200 + 4..], MeshKind::Quarter)?;
+        assert_eq!(v.code, code("543800"));
+        assert_eq!(v.cells(), cells.as_slice());
+        assert_eq!(v.cell(0, 1), Some(&Pattern::Gradient.cell(v.code, 1)));
+
+        let out_path = std::env::temp_dir().join(format!("{}-{}.csv", v.code, std::process::id()));
+        v.save_csv(&out_path)?;
+        let lines = std::fs::read_to_string(&out_path)?.lines().count();
+        std::fs::remove_file(&out_path)?;
+        assert_eq!(lines, 40);
+        Ok(())
+    }
 
+    #[test]
+    fn test_read_cell_patterns() -> Result<()> {
+        let data = Fixture::default()
+            .blocks([("543870", 1)])
+            .pattern(Pattern::Special)
+            .build();
+        let (_, meshes) = read_sequential_block(&data[HEADER_SIZE..], MeshKind::Quarter)?;
+        let cells = meshes[0].cells();
+        assert_eq!(cells[0].rain(), RainValue::NotObserved);
+        assert_eq!(cells[1].rain(), RainValue::OutOfRange);
+        assert_eq!(cells[1].quality_flag(), QualityFlag::from_raw(4));
+        assert_eq!(cells[2].rain(), RainValue::Missing);
+        assert_eq!(cells[3].quality_flag().to_raw(), 6);
+        assert_eq!(cells[3].rain(), RainValue::Rain(0.0));
+
+        let data = Fixture::default()
+            .blocks([("543870", 1)])
+            .pattern(Pattern::Constant {
+                quality: 0x0F,
+                strength: 0x0FFF,
+            })
+            .build();
+        let (_, cell) = read_cell(&data[HEADER_SIZE + 4..])?;
+        assert_eq!(&data[HEADER_SIZE + 4..HEADER_SIZE + 6], &[0xFF, 0xFF]);
+        assert_eq!(cell, XrainCell::new(0x0F, 0x0FFF));
         Ok(())
     }
 
+    #[test]
+    fn test_open_corrupt() {
+        let open = |fixture: Fixture| read_xrain(&fixture.build()).err();
+        let size = Fixture::default().build().len();
+        assert!(matches!(
+            open(Fixture::default().corrupt(Corruption::Truncate(1))),
+            Some(XrainError::Truncated {
+                section: Section::Cell,
+                offset,
+                needed: 1
+            }) if offset == size - 2
+        ));
+        assert!(matches!(
+            open(Fixture::default().corrupt(Corruption::MissingBlocks(2))),
+            Some(XrainError::BlockCountMismatch {
+                declared: 6,
+                found: 4
+            })
+        ));
+        assert!(matches!(
+            open(Fixture::default().corrupt(Corruption::EmptyBlock)),
+            Some(XrainError::EmptyBlock { offset: 64 })
+        ));
+        // 余分なバイトはパースでは無視し、validateで報告する。
+        let data = Fixture::default()
+            .corrupt(Corruption::TrailingBytes(3))
+            .build();
+        assert!(read_xrain(&data).is_ok());
+        assert!(matches!(
+            validate(&data).unwrap().issues(),
+            [
+                ValidationIssue::DataSizeMismatch { .. },
+                ValidationIssue::TrailingBytes { len: 3, .. }
+            ]
+        ));
+    }
+
     #[test]
     fn test_open() -> Result<()> {
-        let mut xrain = open_xrain("KANTO00001-20191011-0000-G000-EL000000")?;
-        println!("{:?}", xrain.keys());
-        let nagano = xrain.get_mut(&5438);
+        let fixture = Fixture::default();
+        let path = fixture.write("KANTO00001-20191011-0000-G000-EL000000")?;
+        let xrain = open_xrain(&path);
+        std::fs::remove_file(&path)?;
+        let xrain = xrain?;
+        assert_eq!(
+            xrain.primary_codes().collect::<Vec<_>>(),
+            vec![
+                PrimaryMeshCode::from_str("5339")?,
+                PrimaryMeshCode::from_str("5438")?,
+                PrimaryMeshCode::from_str("5439")?
+            ]
+        );
+        assert_eq!(xrain.len(), fixture.meshes().len());
+        let nagano = xrain.primary(PrimaryMeshCode::from_str("5438")?);
         assert!(nagano.is_some());
         let nagano = nagano.unwrap();
-        println!("{:?}", nagano.keys());
-
-        let mut merged_vec = Vec::<Array3<u16>>::new();
-        for i in 0..8 {
-            let mut view_vec: Vec<Array3<u16>> = Vec::new();
-            for j in 0..8 {
-                let code: usize = i * 10 + j;
-
-                nagano
-                    .entry(code)
-                    .or_insert_with(|| SecondaryMesh::zeros(0, 0, 0, 0));
-                let arr = nagano.get(&code).unwrap();
-                let arr = Array3::<u16>::from(arr);
-                view_vec.push(arr);
-            }
 
-            let arr_view: Vec<ArrayView3<u16>> = view_vec.iter().map(|f| f.view()).collect();
-
-            let merged = concatenate(Axis(2), arr_view.as_slice()).unwrap();
-            merged_vec.push(merged);
+        // 関東のモザイクのうち5438は北から2番目、西から5番目の1次メッシュ
+        let mosaic = xrain.rain_mosaic()?;
+        assert_eq!(mosaic.shape(), (3200, 3200));
+        let merged_mesh = mosaic.data().slice(s![320..640, 1280..1600]);
+        let stitched = nagano.to_ndarray();
+        for ((i, j), value) in merged_mesh.indexed_iter() {
+            let expected = stitched[(0, i, j)];
+            assert!(*value == expected || (*value == NODATA_RAW && expected == 0));
         }
 
-        let mview: Vec<ArrayView3<u16>> = merged_vec.iter().map(|f| f.view()).rev().collect();
-        let merged_mesh = concatenate(Axis(1), mview.as_slice())?;
+        // 北西端(543870の北西のセル)は行0列0、南端の行(543800)は下の40行。
+        let first = Pattern::Gradient.cell(code("543870"), 0);
+        assert_eq!(merged_mesh[(0, 0)], first.strength());
+        let south = Pattern::Gradient.cell(code("543800"), 1599);
+        assert_eq!(merged_mesh[(319, 39)], south.strength());
+        // データのない行はnodata
+        assert_eq!(merged_mesh[(40, 0)], NODATA_RAW);
 
-        println!("{:?}", merged_mesh);
-        let out_path = PathBuf::from_str("combine.csv")?;
-        let mut wtr = Writer::from_path(out_path)?;
+        let out_path = std::env::temp_dir().join(format!("combine-{}.csv", std::process::id()));
+        let mut wtr = Writer::from_path(&out_path)?;
 
-        let xsize: usize = merged_mesh.shape()[2];
-        let ysize: usize = merged_mesh.shape()[1];
+        let xsize: usize = merged_mesh.shape()[1];
+        let ysize: usize = merged_mesh.shape()[0];
 
         for i in 0..ysize {
-            let mut vline = Vec::<u16>::new();
-            vline.reserve(ysize);
+            let mut vline = Vec::<u16>::with_capacity(ysize);
             for j in 0..xsize {
-                let value = merged_mesh.get((0, i, j)).unwrap();
+                let value = merged_mesh.get((i, j)).unwrap();
                 vline.push(*value);
             }
             wtr.serialize(vline)?;
         }
         wtr.flush()?;
+        let lines = std::fs::read_to_string(&out_path)?.lines().count();
+        std::fs::remove_file(&out_path)?;
+        assert_eq!(lines, 320);
         Ok(())
     }
 
     #[test]
     fn test_ndarray() -> Result<()> {
-        let data = load_file_as_slice("KANTO00001-20191011-0000-G000-EL000000")?;
+        let data = Fixture::default().build();
         let (input, header) = read_header(data.as_slice())?;
 
-        let mut buf = input;
+        let buf = input;
 
-        let mut i: u16 = 0;
-        let (input_internal, meshes) = read_sequential_block(buf)?;
+        let (_input_internal, meshes) = read_sequential_block(buf, header.mesh_kind())?;
         let mut meshes = meshes;
+        assert_eq!(meshes.len(), 1);
 
-        if meshes.is_empty() {
-            println!("It is empty");
-        } else {
-            meshes.sort_by(|lhs, rhs| {
-                return lhs.secondary_lon_code.cmp(&rhs.secondary_lon_code);
-            });
+        meshes.sort_by_key(|v| v.code.x());
 
-            let msh = meshes.pop().unwrap();
-            let arr = Array3::<u16>::from(msh);
-            println!("{:?}", arr);
-        }
+        let msh = meshes.pop().unwrap();
+        let msh_code = msh.code();
+        let arr = msh.ndarray();
+        assert_eq!(arr, Array3::<u16>::from(msh));
+        assert_eq!(arr.shape(), &[2, 40, 40]);
+        let cell = Pattern::Gradient.cell(msh_code, 41);
+        assert_eq!(arr[(0, 1, 1)], cell.strength());
+        assert_eq!(arr[(1, 1, 1)], cell.quality());
 
+        let mesh = SecondaryMesh::new(code("543871"), vec![XrainCell::default(); 100])?;
+        assert_eq!(Array3::<u16>::from(&mesh).shape(), &[2, 10, 10]);
         Ok(())
     }
 
@@ -833,13 +1261,13 @@ mod tests {
             println!("{}", idx);
             idx += 1;
         }
-        for i in 0..10 {}
+        for _i in 0..10 {}
         Ok(())
     }
 
     #[test]
     fn test_default_nadarry() -> Result<()> {
-        let mut arr = Array3::<u16>::default((1, 40, 40));
+        let arr = Array3::<u16>::default((1, 40, 40));
         println!("{:?}", arr);
         println!("{:?}", concatenate(Axis(1), &[arr.view(), arr.view()]));
         Ok(())
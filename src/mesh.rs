@@ -0,0 +1,345 @@
+//! JIS X 0410 地域メッシュコード
+//!
+//! 1次メッシュ(約80km)を縦横8分割したものが2次メッシュ(約10km)、
+//! 2次メッシュを縦横40分割したものが1/4 3次メッシュ(約250m)でXRAINの1セルにあたる。
+
+use std::fmt;
+use std::str::FromStr;
+
+/// 2次メッシュの1辺のセル数
+pub(crate) const CELLS_PER_SIDE: u8 = 40;
+
+/// Error returned when a mesh code string cannot be parsed.
+///
+/// メッシュコードの文字列が不正。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMeshCodeError {
+    input: String,
+}
+
+impl fmt::Display for ParseMeshCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid mesh code: {:?}", self.input)
+    }
+}
+
+impl std::error::Error for ParseMeshCodeError {}
+
+/// 数字だけからなる`len`桁の文字列を各桁に分解する。
+fn digits(s: &str, len: usize) -> Result<Vec<u8>, ParseMeshCodeError> {
+    if s.len() != len || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseMeshCodeError { input: s.into() });
+    }
+    Ok(s.bytes().map(|b| b - b'0').collect())
+}
+
+/// First-level mesh code (about 80 km square), e.g. `5438`.
+///
+/// 1次メッシュコード
+/// lat:上2桁(緯度*1.5)、lon:下2桁(経度-100)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PrimaryMeshCode {
+    lat: u8,
+    lon: u8,
+}
+
+impl PrimaryMeshCode {
+    /// 上2桁と下2桁から作成する。どちらかが2桁に収まらない場合は`None`。
+    pub fn new(lat: u8, lon: u8) -> Option<Self> {
+        if lat > 99 || lon > 99 {
+            return None;
+        }
+        Some(Self { lat, lon })
+    }
+
+    /// 上2桁
+    pub fn lat(&self) -> u8 {
+        self.lat
+    }
+
+    /// 下2桁
+    pub fn lon(&self) -> u8 {
+        self.lon
+    }
+
+    /// 4桁のコード
+    pub fn code(&self) -> u32 {
+        u32::from(self.lat) * 100 + u32::from(self.lon)
+    }
+
+    /// 北に`dlat`、東に`dlon`個ずれた1次メッシュ
+    pub fn offset(&self, dlat: i32, dlon: i32) -> Option<Self> {
+        let lat = u8::try_from(i32::from(self.lat) + dlat).ok()?;
+        let lon = u8::try_from(i32::from(self.lon) + dlon).ok()?;
+        Self::new(lat, lon)
+    }
+
+    /// 1次メッシュ内の2次メッシュ。y:南から北、x:西から東(0..8)
+    pub fn secondary(&self, y: u8, x: u8) -> Option<SecondaryMeshCode> {
+        SecondaryMeshCode::new(*self, y, x)
+    }
+
+    /// 含まれる64個の2次メッシュ(コード順)
+    pub fn secondaries(&self) -> impl Iterator<Item = SecondaryMeshCode> {
+        let primary = *self;
+        (0..8).flat_map(move |y| (0..8).map(move |x| SecondaryMeshCode { primary, y, x }))
+    }
+}
+
+impl fmt::Display for PrimaryMeshCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}{:02}", self.lat, self.lon)
+    }
+}
+
+impl FromStr for PrimaryMeshCode {
+    type Err = ParseMeshCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let d = digits(s, 4)?;
+        Ok(Self {
+            lat: d[0] * 10 + d[1],
+            lon: d[2] * 10 + d[3],
+        })
+    }
+}
+
+/// Second-level mesh code (about 10 km square), e.g. `543870`.
+///
+/// 2次メッシュコード
+/// y:上から5桁目(南から北)、x:上から6桁目(西から東)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SecondaryMeshCode {
+    primary: PrimaryMeshCode,
+    y: u8,
+    x: u8,
+}
+
+impl SecondaryMeshCode {
+    /// y,xが0..8に収まらない場合は`None`。
+    pub fn new(primary: PrimaryMeshCode, y: u8, x: u8) -> Option<Self> {
+        if y > 7 || x > 7 {
+            return None;
+        }
+        Some(Self { primary, y, x })
+    }
+
+    /// 親の1次メッシュ
+    pub fn primary(&self) -> PrimaryMeshCode {
+        self.primary
+    }
+
+    /// 南から北への番号(0..8)
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    /// 西から東への番号(0..8)
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    /// 6桁のコード
+    pub fn code(&self) -> u32 {
+        self.primary.code() * 100 + u32::from(self.y) * 10 + u32::from(self.x)
+    }
+
+    /// 北に`dy`、東に`dx`個ずれた2次メッシュ。1次メッシュの境界をまたいでもよい。
+    pub fn offset(&self, dy: i32, dx: i32) -> Option<Self> {
+        let y = i32::from(self.y) + dy;
+        let x = i32::from(self.x) + dx;
+        let primary = self.primary.offset(y.div_euclid(8), x.div_euclid(8))?;
+        Some(Self {
+            primary,
+            y: y.rem_euclid(8) as u8,
+            x: x.rem_euclid(8) as u8,
+        })
+    }
+
+    /// `row`行`col`列のセル。0行目が北端、0列目が西端(0..40)。
+    pub fn cell(&self, row: u8, col: u8) -> Option<QuarterTertiaryMeshCode> {
+        QuarterTertiaryMeshCode::new(*self, row, col)
+    }
+
+    /// 含まれる1600個のセル。ファイルと同じく北西から東へ、北から南へ並ぶ。
+    pub fn cells(&self) -> impl Iterator<Item = QuarterTertiaryMeshCode> {
+        let secondary = *self;
+        (0..CELLS_PER_SIDE).flat_map(move |row| {
+            (0..CELLS_PER_SIDE).map(move |col| QuarterTertiaryMeshCode {
+                secondary,
+                row,
+                col,
+            })
+        })
+    }
+}
+
+impl fmt::Display for SecondaryMeshCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.primary, self.y, self.x)
+    }
+}
+
+impl FromStr for SecondaryMeshCode {
+    type Err = ParseMeshCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let d = digits(s, 6)?;
+        let primary = s[..4].parse()?;
+        Self::new(primary, d[4], d[5]).ok_or_else(|| ParseMeshCodeError { input: s.into() })
+    }
+}
+
+/// Quarter third-level mesh code (about 250 m square), the area of one `XrainCell`.
+///
+/// 1/4 3次メッシュコード(10桁)
+/// 2次メッシュ内の位置をセルの行、列で持つ。0行目が北端、0列目が西端。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QuarterTertiaryMeshCode {
+    secondary: SecondaryMeshCode,
+    row: u8,
+    col: u8,
+}
+
+impl QuarterTertiaryMeshCode {
+    /// row,colが0..40に収まらない場合は`None`。
+    pub fn new(secondary: SecondaryMeshCode, row: u8, col: u8) -> Option<Self> {
+        if row >= CELLS_PER_SIDE || col >= CELLS_PER_SIDE {
+            return None;
+        }
+        Some(Self {
+            secondary,
+            row,
+            col,
+        })
+    }
+
+    /// 親の2次メッシュ
+    pub fn secondary(&self) -> SecondaryMeshCode {
+        self.secondary
+    }
+
+    /// 2次メッシュ内の行(北から南)
+    pub fn row(&self) -> u8 {
+        self.row
+    }
+
+    /// 2次メッシュ内の列(西から東)
+    pub fn col(&self) -> u8 {
+        self.col
+    }
+
+    /// `SecondaryMesh::cells`でのインデックス
+    pub fn index(&self) -> usize {
+        usize::from(self.row) * usize::from(CELLS_PER_SIDE) + usize::from(self.col)
+    }
+
+    /// 南からのセル番号
+    fn south(&self) -> u8 {
+        CELLS_PER_SIDE - 1 - self.row
+    }
+
+    /// 8桁の3次メッシュコード
+    pub fn tertiary_code(&self) -> u32 {
+        self.secondary.code() * 100 + u32::from(self.south() / 4) * 10 + u32::from(self.col / 4)
+    }
+
+    /// 10桁のコード
+    pub fn code(&self) -> u64 {
+        let half = (self.south() % 4) / 2 * 2 + (self.col % 4) / 2 + 1;
+        let quarter = (self.south() % 2) * 2 + self.col % 2 + 1;
+        u64::from(self.tertiary_code()) * 100 + u64::from(half) * 10 + u64::from(quarter)
+    }
+
+    /// 北に`dy`、東に`dx`セルずれたセル。2次メッシュの境界をまたいでもよい。
+    pub fn offset(&self, dy: i32, dx: i32) -> Option<Self> {
+        let side = i32::from(CELLS_PER_SIDE);
+        let south = i32::from(self.south()) + dy;
+        let col = i32::from(self.col) + dx;
+        let secondary = self
+            .secondary
+            .offset(south.div_euclid(side), col.div_euclid(side))?;
+        Some(Self {
+            secondary,
+            row: (side - 1 - south.rem_euclid(side)) as u8,
+            col: col.rem_euclid(side) as u8,
+        })
+    }
+}
+
+impl fmt::Display for QuarterTertiaryMeshCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:010}", self.code())
+    }
+}
+
+impl FromStr for QuarterTertiaryMeshCode {
+    type Err = ParseMeshCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseMeshCodeError { input: s.into() };
+        let d = digits(s, 10)?;
+        let secondary: SecondaryMeshCode = s[..6].parse()?;
+        if !(1..=4).contains(&d[8]) || !(1..=4).contains(&d[9]) {
+            return Err(err());
+        }
+        let south = d[6] * 4 + (d[8] - 1) / 2 * 2 + (d[9] - 1) / 2;
+        let col = d[7] * 4 + (d[8] - 1) % 2 * 2 + (d[9] - 1) % 2;
+        Self::new(secondary, CELLS_PER_SIDE - 1 - south, col).ok_or_else(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        let code: SecondaryMeshCode = "543870".parse().unwrap();
+        assert_eq!(code.primary(), PrimaryMeshCode::new(54, 38).unwrap());
+        assert_eq!((code.y(), code.x()), (7, 0));
+        assert_eq!(code.to_string(), "543870");
+        assert_eq!(code.code(), 543870);
+        assert!("543880".parse::<SecondaryMeshCode>().is_err());
+        assert!("54387".parse::<SecondaryMeshCode>().is_err());
+        assert!("5438a0".parse::<SecondaryMeshCode>().is_err());
+        assert_eq!(
+            "0934".parse::<PrimaryMeshCode>().unwrap().to_string(),
+            "0934"
+        );
+    }
+
+    #[test]
+    fn test_offset() {
+        let code: SecondaryMeshCode = "543877".parse().unwrap();
+        assert_eq!(code.offset(1, 1).unwrap().to_string(), "553900");
+        assert_eq!(code.offset(-8, -8).unwrap().to_string(), "533777");
+        let primary = PrimaryMeshCode::new(0, 0).unwrap();
+        assert!(primary.offset(-1, 0).is_none());
+        assert_eq!(primary.secondaries().count(), 64);
+    }
+
+    #[test]
+    fn test_quarter_tertiary() {
+        let secondary: SecondaryMeshCode = "543870".parse().unwrap();
+        // 南西端のセル
+        let sw = secondary.cell(39, 0).unwrap();
+        assert_eq!(sw.to_string(), "5438700011");
+        assert_eq!(sw.tertiary_code(), 54387000);
+        // 北東端のセル
+        let ne = secondary.cell(0, 39).unwrap();
+        assert_eq!(ne.to_string(), "5438709944");
+        assert_eq!(ne.index(), 39);
+        for cell in secondary.cells() {
+            assert_eq!(
+                cell.to_string().parse::<QuarterTertiaryMeshCode>(),
+                Ok(cell)
+            );
+        }
+        assert_eq!(sw.offset(-1, -1).unwrap().to_string(), "5437679944");
+        assert_eq!(ne.offset(1, 0).unwrap().secondary().to_string(), "553800");
+        assert!("5438700050".parse::<QuarterTertiaryMeshCode>().is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! ファイル全体を1枚の配列にする
+//!
+//! ヘッダーの南西端から北東端の1次メッシュまでを覆う配列に2次メッシュを並べ、
+//! データのない2次メッシュはnodataで埋める。
+
+use crate::geo::{PRIMARY_LAT, PRIMARY_LON};
+use crate::{
+    s, AffineTransform, Array2, LatLonBounds, PrimaryMeshCode, XrainCell, XrainDataset, XrainError,
+};
+
+/// 整数の配列でデータのない2次メッシュの値。12bitの雨量、4bitの品質とは重ならない。
+pub const NODATA_RAW: u16 = u16::MAX;
+
+/// 配列のセル数の上限。250mメッシュで16x16個の1次メッシュ分。
+pub const MAX_MOSAIC_CELLS: usize = (16 * 320) * (16 * 320);
+
+/// A georeferenced grid covering the header's bounding primary meshes.
+///
+/// 南西端から北東端の1次メッシュまでを覆う配列。0行目が北端、0列目が西端。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mosaic<T> {
+    data: Array2<T>,
+    transform: AffineTransform,
+    nodata: T,
+    bottom_left: PrimaryMeshCode,
+    top_right: PrimaryMeshCode,
+}
+
+impl<T> Mosaic<T> {
+    /// (行, 列)の配列
+    pub fn data(&self) -> &Array2<T> {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Array2<T> {
+        self.data
+    }
+
+    /// 行列から緯度経度への変換
+    pub fn transform(&self) -> AffineTransform {
+        self.transform
+    }
+
+    /// データのない2次メッシュの値
+    pub fn nodata(&self) -> &T {
+        &self.nodata
+    }
+
+    /// 南西端の1次メッシュコード
+    pub fn bottom_left(&self) -> PrimaryMeshCode {
+        self.bottom_left
+    }
+
+    /// 北東端の1次メッシュコード
+    pub fn top_right(&self) -> PrimaryMeshCode {
+        self.top_right
+    }
+
+    /// (行数, 列数)
+    pub fn shape(&self) -> (usize, usize) {
+        self.data.dim()
+    }
+
+    /// 配列全体の緯度経度の範囲
+    pub fn bounds(&self) -> LatLonBounds {
+        let (rows, cols) = self.shape();
+        let (south, east) = self.transform.lat_lon(rows as f64, cols as f64);
+        LatLonBounds {
+            south,
+            west: self.transform.west,
+            north: self.transform.north,
+            east,
+        }
+    }
+
+    /// 緯度経度を含むセルの値。範囲外なら`None`。
+    pub fn get(&self, lat: f64, lon: f64) -> Option<&T> {
+        let (row, col) = self.transform.row_col(lat, lon)?;
+        self.data.get((row, col))
+    }
+}
+
+impl XrainDataset {
+    /// セルを`f`で変換して1枚の配列にする。
+    /// ヘッダーの範囲外の2次メッシュと、セル数がヘッダーのメッシュの大きさと異なる2次メッシュは使わない。
+    ///
+    /// 南西端が北東端より北か東なら`XrainError::InvalidExtent`、
+    /// 配列のセル数が`MAX_MOSAIC_CELLS`を超えるなら`XrainError::MosaicTooLarge`。
+    pub fn mosaic<T, F>(&self, nodata: T, f: F) -> Result<Mosaic<T>, XrainError>
+    where
+        T: Clone,
+        F: Fn(&XrainCell) -> T,
+    {
+        let bottom_left = self.header().bottom_left();
+        let top_right = self.header().top_right();
+        if bottom_left.lat() > top_right.lat() || bottom_left.lon() > top_right.lon() {
+            return Err(XrainError::InvalidExtent {
+                bottom_left,
+                top_right,
+            });
+        }
+        let side = self.header().mesh_kind().cells_per_side();
+        let rows = usize::from(top_right.lat() - bottom_left.lat() + 1) * 8 * side;
+        let cols = usize::from(top_right.lon() - bottom_left.lon() + 1) * 8 * side;
+        if rows * cols > MAX_MOSAIC_CELLS {
+            return Err(XrainError::MosaicTooLarge {
+                bottom_left,
+                top_right,
+            });
+        }
+        let mut data = Array2::from_elem((rows, cols), nodata.clone());
+
+        for mesh in self.secondary_meshes() {
+            let code = mesh.code();
+            if !self.header().covers(code.primary()) || mesh.cells_per_side() != side {
+                continue;
+            }
+            let row = (usize::from(top_right.lat() - code.primary().lat()) * 8
+                + usize::from(7 - code.y()))
+                * side;
+            let col = (usize::from(code.primary().lon() - bottom_left.lon()) * 8
+                + usize::from(code.x()))
+                * side;
+            let cells = mesh.cells();
+            data.slice_mut(s![row..row + side, col..col + side])
+                .indexed_iter_mut()
+                .for_each(|((r, c), value)| *value = f(&cells[r * side + c]));
+        }
+
+        let cells_per_primary = (8 * side) as f64;
+        let transform = AffineTransform {
+            west: bottom_left.bounds().west,
+            lon_step: PRIMARY_LON / cells_per_primary,
+            north: top_right.bounds().north,
+            lat_step: -PRIMARY_LAT / cells_per_primary,
+        };
+        Ok(Mosaic {
+            data,
+            transform,
+            nodata,
+            bottom_left,
+            top_right,
+        })
+    }
+
+    /// 雨量データ(12bit)の配列。データのない2次メッシュは`NODATA_RAW`。
+    pub fn rain_mosaic(&self) -> Result<Mosaic<u16>, XrainError> {
+        self.mosaic(NODATA_RAW, |cell| cell.strength())
+    }
+
+    /// 品質管理情報(4bit)の配列。データのない2次メッシュは`NODATA_RAW`。
+    pub fn quality_mosaic(&self) -> Result<Mosaic<u16>, XrainError> {
+        self.mosaic(NODATA_RAW, |cell| cell.quality())
+    }
+
+    /// 雨量(mm/h)の配列。特殊値とデータのない2次メッシュはNaN。
+    pub fn rain_mm_mosaic(&self) -> Result<Mosaic<f64>, XrainError> {
+        self.mosaic(f64::NAN, |cell| cell.rain().to_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{code, Fixture, Pattern};
+
+    #[test]
+    fn test_mosaic() {
+        let fixture = Fixture::default().blocks([("543877", 2), ("463400", 1)]);
+        let dataset = XrainDataset::from_bytes(&fixture.build()).unwrap();
+        let mosaic = dataset.rain_mosaic().unwrap();
+        // 関東は4634から5543の10x10個の1次メッシュ
+        assert_eq!(mosaic.shape(), (3200, 3200));
+        assert_eq!(*mosaic.nodata(), NODATA_RAW);
+
+        let b = mosaic.bounds();
+        assert!((b.south - 46.0 * 2.0 / 3.0).abs() < 1e-9);
+        assert!((b.west - 134.0).abs() < 1e-9);
+        assert!((b.north - 56.0 * 2.0 / 3.0).abs() < 1e-9);
+        assert!((b.east - 144.0).abs() < 1e-9);
+
+        // 各セルの中心の値がそのセルの値
+        for (name, index) in [
+            ("543877", 0),
+            ("543877", 1599),
+            ("543970", 41),
+            ("463400", 1599),
+        ] {
+            let mesh = code(name);
+            let cell = mesh.cell((index / 40) as u8, (index % 40) as u8).unwrap();
+            let (lat, lon) = cell.center();
+            assert_eq!(
+                mosaic.get(lat, lon),
+                Some(&Pattern::Gradient.cell(mesh, index).strength()),
+                "{} {}",
+                name,
+                index
+            );
+        }
+        // 南西端の行と列
+        let last = Pattern::Gradient.cell(code("463400"), 1560).strength();
+        assert_eq!(mosaic.data()[(3199, 0)], last);
+        assert_eq!(mosaic.data()[(0, 0)], NODATA_RAW);
+        assert_eq!(
+            mosaic.data().iter().filter(|&&v| v != NODATA_RAW).count(),
+            3 * 1600
+        );
+        assert!(mosaic.get(36.0, 133.9).is_none());
+
+        let rain = dataset.rain_mm_mosaic().unwrap();
+        assert!(rain.data()[(0, 0)].is_nan());
+        assert_eq!(rain.transform(), mosaic.transform());
+    }
+
+    #[test]
+    fn test_mosaic_extent() {
+        // 33x33個の1次メッシュは上限を超える
+        let dataset = XrainDataset::from_bytes(
+            &Fixture::default()
+                .extent("3622", "6854")
+                .blocks([("543870", 1)])
+                .build(),
+        )
+        .unwrap();
+        assert!(matches!(
+            dataset.rain_mosaic(),
+            Err(XrainError::MosaicTooLarge { .. })
+        ));
+        // 東西に33個でも南北に1個ならセル数は上限より少ない
+        let dataset = XrainDataset::from_bytes(
+            &Fixture::default()
+                .extent("5422", "5454")
+                .blocks([("543870", 1)])
+                .build(),
+        )
+        .unwrap();
+        assert_eq!(dataset.rain_mosaic().unwrap().shape(), (320, 33 * 320));
+    }
+}
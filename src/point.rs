@@ -0,0 +1,205 @@
+//! 緯度経度を指定した雨量の取得
+
+use crate::{
+    LatLonBounds, PrimaryMeshCode, QuarterTertiaryMeshCode, RainValue, XrainDataset, XrainHeader,
+};
+
+/// How to sample the grid at a point.
+///
+/// 点での値の求め方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sampling {
+    /// 点を含むセルの値
+    #[default]
+    Nearest,
+    /// 周囲4セルの中心からの双線形補間。データのないセルと特殊値のセルは除いて重みを付け直す。
+    Bilinear,
+}
+
+/// Result of a point query.
+///
+/// 点での雨量
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointRain {
+    /// 値が得られた。qualityは点を含むセルの品質。
+    /// 補間に使えるセルがない場合、rainは点を含むセルの特殊値になる。
+    Sampled {
+        cell: QuarterTertiaryMeshCode,
+        rain: RainValue,
+        quality: u16,
+    },
+    /// ファイルの範囲内だが、点を含む2次メッシュのデータがない。
+    NoData { cell: QuarterTertiaryMeshCode },
+    /// ファイルの南西端から北東端の1次メッシュの範囲外。
+    NotCovered,
+}
+
+impl XrainHeader {
+    /// 南西端から北東端の範囲に1次メッシュが含まれるか。
+    pub fn covers(&self, code: PrimaryMeshCode) -> bool {
+        let bl = self.bottom_left();
+        let tr = self.top_right();
+        (bl.lat()..=tr.lat()).contains(&code.lat()) && (bl.lon()..=tr.lon()).contains(&code.lon())
+    }
+
+    /// 南西端から北東端の1次メッシュまでの緯度経度範囲
+    pub fn bounds(&self) -> LatLonBounds {
+        let bottom_left = self.bottom_left().bounds();
+        let top_right = self.top_right().bounds();
+        LatLonBounds {
+            south: bottom_left.south,
+            west: bottom_left.west,
+            north: top_right.north,
+            east: top_right.east,
+        }
+    }
+}
+
+impl XrainDataset {
+    /// 点を含むセルの雨量と品質
+    pub fn rain_at(&self, lat: f64, lon: f64) -> PointRain {
+        self.rain_at_with(lat, lon, Sampling::Nearest)
+    }
+
+    /// `sampling`で求めた点の雨量
+    pub fn rain_at_with(&self, lat: f64, lon: f64, sampling: Sampling) -> PointRain {
+        if !self.header().bounds().contains(lat, lon) {
+            return PointRain::NotCovered;
+        }
+        let cell = match QuarterTertiaryMeshCode::from_lat_lon(lat, lon) {
+            Some(cell) => cell,
+            None => return PointRain::NotCovered,
+        };
+        let nearest = match self.cell(cell) {
+            Some(c) => c,
+            None => return PointRain::NoData { cell },
+        };
+        let rain = match sampling {
+            Sampling::Nearest => nearest.rain(),
+            Sampling::Bilinear => self
+                .bilinear(cell, lat, lon)
+                .map_or(nearest.rain(), RainValue::Rain),
+        };
+        PointRain::Sampled {
+            cell,
+            rain,
+            quality: nearest.quality(),
+        }
+    }
+
+    /// 周囲4セルの中心からの双線形補間。セルの大きさは`XrainHeader::mesh_kind`に従う。
+    fn bilinear(&self, cell: QuarterTertiaryMeshCode, lat: f64, lon: f64) -> Option<f64> {
+        let kind = self.header().mesh_kind();
+        let scale = kind.scale() as i32;
+        // 点を含むセルの北西と南東の角の1/4 3次メッシュ
+        let origin = kind.cell_origin(cell);
+        let corner = origin.offset(1 - scale, scale - 1)?;
+        let bounds = LatLonBounds {
+            south: corner.bounds().south,
+            west: origin.bounds().west,
+            north: origin.bounds().north,
+            east: corner.bounds().east,
+        };
+        // セル中心からのずれ(-0.5..0.5)
+        let ty = (lat - bounds.south) / (bounds.north - bounds.south) - 0.5;
+        let tx = (lon - bounds.west) / (bounds.east - bounds.west) - 0.5;
+        // 南側と西側のセルへのずれと、北側、東側のセルの重み
+        let (dy, wy) = if ty < 0.0 { (-1, ty + 1.0) } else { (0, ty) };
+        let (dx, wx) = if tx < 0.0 { (-1, tx + 1.0) } else { (0, tx) };
+
+        let mut sum = 0.0;
+        let mut weight = 0.0;
+        for (oy, w_lat) in [(dy, 1.0 - wy), (dy + 1, wy)] {
+            for (ox, w_lon) in [(dx, 1.0 - wx), (dx + 1, wx)] {
+                let w = w_lat * w_lon;
+                let value = origin
+                    .offset(oy * scale, ox * scale)
+                    .and_then(|c| self.cell(c))
+                    .and_then(|c| c.rain().mm_per_hour());
+                if let Some(value) = value {
+                    sum += w * value;
+                    weight += w;
+                }
+            }
+        }
+        if weight > 0.0 {
+            Some(sum / weight)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+    use crate::{SecondaryMesh, SecondaryMeshCode, XrainCell};
+
+    fn dataset() -> XrainDataset {
+        let header = Fixture::default().extent("5438", "5438").header();
+        // 西から東へ雨量が1mm/hずつ増える。最後の行は欠測。
+        let cells = (0..1600)
+            .map(|i| match i {
+                1560.. => XrainCell::new(1, 0xFFF),
+                _ => XrainCell::new(1, (i % 40) as u16 * 10),
+            })
+            .collect();
+        let mesh = SecondaryMesh::new("543870".parse().unwrap(), cells).unwrap();
+        XrainDataset::new(header, vec![mesh]).unwrap()
+    }
+
+    #[test]
+    fn test_nearest() {
+        let dataset = dataset();
+        let secondary: SecondaryMeshCode = "543870".parse().unwrap();
+        let cell = secondary.cell(3, 5).unwrap();
+        let (lat, lon) = cell.center();
+        assert_eq!(
+            dataset.rain_at(lat, lon),
+            PointRain::Sampled {
+                cell,
+                rain: RainValue::Rain(5.0),
+                quality: 1
+            }
+        );
+        let (lat, lon) = secondary.cell(39, 5).unwrap().center();
+        assert!(matches!(
+            dataset.rain_at(lat, lon),
+            PointRain::Sampled {
+                rain: RainValue::Missing,
+                ..
+            }
+        ));
+        assert!(matches!(
+            dataset.rain_at(36.1, 138.5),
+            PointRain::NoData { .. }
+        ));
+        assert_eq!(dataset.rain_at(35.0, 138.5), PointRain::NotCovered);
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let dataset = dataset();
+        let secondary: SecondaryMeshCode = "543870".parse().unwrap();
+        let west = secondary.cell(3, 5).unwrap().center();
+        let east = secondary.cell(3, 6).unwrap().center();
+        let lon = west.1 * 0.75 + east.1 * 0.25;
+        match dataset.rain_at_with(west.0, lon, Sampling::Bilinear) {
+            PointRain::Sampled {
+                rain: RainValue::Rain(rain),
+                ..
+            } => assert!((rain - 5.25).abs() < 1e-6),
+            other => panic!("{:?}", other),
+        }
+        // 南側の欠測セルは補間に使わない
+        let south = secondary.cell(39, 5).unwrap().bounds();
+        match dataset.rain_at_with(south.north - 1e-6, west.1, Sampling::Bilinear) {
+            PointRain::Sampled {
+                rain: RainValue::Rain(rain),
+                ..
+            } => assert!((rain - 5.0).abs() < 1e-6),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! パーサーとエンコーダーの性質のテスト
+//!
+//! 任意のバイト列でパニックしないこと、書き出したファイルを読み戻すと元に戻ること、
+//! 読み進めたバイト数がヘッダーのデータサイズと一致することを確かめる。
+
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+
+use crate::fixture::{Corruption, Fixture};
+use crate::{
+    read_block_header, read_cell, read_header, read_sequential_block, read_xrain, validate,
+    MeshIndex, MeshKind, ObservationTime, PrimaryMeshCode, SecondaryMesh, SecondaryMeshCode,
+    StreamEvent, XrainCell, XrainDataset, XrainHeader, XrainStreamParser, XrainView,
+};
+
+fn cell() -> impl Strategy<Value = XrainCell> {
+    (0u16..16, 0u16..4096).prop_map(|(quality, strength)| XrainCell::new(quality, strength))
+}
+
+/// 南西端と北東端の1次メッシュ。1辺は3つまで。
+fn extent() -> impl Strategy<Value = (PrimaryMeshCode, PrimaryMeshCode)> {
+    (20u8..68, 22u8..54, 0u8..3, 0u8..3).prop_map(|(lat, lon, height, width)| {
+        (
+            PrimaryMeshCode::new(lat, lon).unwrap(),
+            PrimaryMeshCode::new(lat + height, lon + width).unwrap(),
+        )
+    })
+}
+
+/// ヘッダーの南西端から北東端の範囲内の2次メッシュ
+fn secondary_code(header: &XrainHeader) -> impl Strategy<Value = SecondaryMeshCode> {
+    let (bl, tr) = (header.bottom_left(), header.top_right());
+    (bl.lat()..=tr.lat(), bl.lon()..=tr.lon(), 0u8..8, 0u8..8).prop_map(|(lat, lon, y, x)| {
+        PrimaryMeshCode::new(lat, lon)
+            .and_then(|p| p.secondary(y, x))
+            .unwrap()
+    })
+}
+
+fn header() -> impl Strategy<Value = XrainHeader> {
+    (
+        any::<u8>(),
+        extent(),
+        any::<u16>(),
+        (2000u16..2100, 1u8..13, 1u8..29, 0u8..24, 0u8..60, 0u8..60),
+    )
+        .prop_map(
+            |(
+                owner,
+                (bottom_left, top_right),
+                data_kind3,
+                (year, month, day, hour, minute, second),
+            )| {
+                XrainHeader::builder()
+                    .owner(owner)
+                    .extent(bottom_left, top_right)
+                    .data_kind3(data_kind3)
+                    .datetime(ObservationTime::new(year, month, day, hour, minute, second).unwrap())
+                    .build()
+                    .unwrap()
+            },
+        )
+}
+
+/// ヘッダーとその範囲内の2次メッシュ
+fn dataset() -> impl Strategy<Value = XrainDataset> {
+    header().prop_flat_map(|header| {
+        let cells = header.mesh_kind().cells_per_mesh();
+        btree_map(secondary_code(&header), vec(cell(), cells), 0..12).prop_map(move |meshes| {
+            XrainDataset::new(
+                header.clone(),
+                meshes
+                    .into_iter()
+                    .map(|(code, cells)| SecondaryMesh::new(code, cells).unwrap()),
+            )
+            .unwrap()
+        })
+    })
+}
+
+/// エラーかデータの終わりまでストリームパーサーを進める。
+fn drain(parser: &mut XrainStreamParser) {
+    while let Ok(event) = parser.next_event() {
+        if matches!(event, StreamEvent::Incomplete(_) | StreamEvent::Done) {
+            break;
+        }
+    }
+}
+
+/// すべての読み込み経路に通す。結果は問わない。
+fn parse_everything(bytes: &[u8]) {
+    let _ = read_header(bytes);
+    let _ = read_block_header(bytes);
+    let _ = read_cell(bytes);
+    let _ = read_xrain(bytes);
+    let _ = validate(bytes);
+    let _ = MeshIndex::from_bytes(bytes);
+    if let Ok(view) = XrainView::new(bytes) {
+        let _ = view.to_dataset();
+    }
+    let mut parser = XrainStreamParser::new();
+    parser.feed(bytes);
+    drain(&mut parser);
+    let _ = parser.finish();
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn prop_round_trip(dataset in dataset()) {
+        let bytes = dataset.to_bytes().unwrap();
+        let decoded = XrainDataset::from_bytes(&bytes).unwrap();
+        prop_assert_eq!(
+            decoded.secondary_meshes().collect::<Vec<_>>(),
+            dataset.secondary_meshes().collect::<Vec<_>>()
+        );
+        prop_assert_eq!(decoded.header().datetime(), dataset.header().datetime());
+        prop_assert_eq!(decoded.header().data_kind3(), dataset.header().data_kind3());
+        prop_assert_eq!(decoded.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn prop_consumed_len(dataset in dataset()) {
+        let bytes = dataset.to_bytes().unwrap();
+        let report = validate(&bytes).unwrap();
+        prop_assert!(report.issues().is_empty(), "{:?}", report.issues());
+        prop_assert_eq!(report.header().data_size() as usize, bytes.len());
+
+        let (mut buf, header) = read_header(&bytes).unwrap();
+        for _ in 0..header.block_num() {
+            buf = read_sequential_block(buf, header.mesh_kind()).unwrap().0;
+        }
+        prop_assert!(buf.is_empty());
+
+        let mut parser = XrainStreamParser::new();
+        parser.feed(&bytes);
+        drain(&mut parser);
+        prop_assert!(parser.is_done());
+        prop_assert_eq!(parser.position(), bytes.len());
+    }
+
+    #[test]
+    fn prop_cell_round_trip(cells in vec(cell(), 1600)) {
+        let mesh = SecondaryMesh::new(SecondaryMeshCode::default(), cells).unwrap();
+        let mut bytes = Vec::new();
+        crate::write_single_block(&mut bytes, &mesh).unwrap();
+        let (rest, decoded) = crate::read_single_block(&bytes, MeshKind::Quarter).unwrap();
+        prop_assert!(rest.is_empty());
+        prop_assert_eq!(decoded.as_slice(), mesh.cells());
+    }
+}
+
+proptest! {
+    #[test]
+    fn prop_arbitrary_bytes(bytes in vec(any::<u8>(), 0..512)) {
+        parse_everything(&bytes);
+    }
+
+    #[test]
+    fn prop_corrupted_file(
+        edits in vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+        cut in 0usize..64,
+    ) {
+        let mut bytes = Fixture::default()
+            .blocks([("543870", 2), ("543900", 1)])
+            .corrupt(Corruption::Truncate(cut))
+            .build();
+        for (index, value) in edits {
+            let i = index.index(bytes.len());
+            bytes[i] = value;
+        }
+        parse_everything(&bytes);
+    }
+}
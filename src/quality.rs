@@ -0,0 +1,107 @@
+//! 品質管理情報(4bit)
+//!
+//! 値ごとの意味は仕様書で未確認なので名前を付けない(READMEの「未対応」)。
+//! 解析に使える値は呼び出し側で選び、`quality_mask`に条件として渡す。
+
+use crate::{s, Array2, PrimaryMesh, PrimaryMeshCode, SecondaryMesh, XrainDataset};
+
+/// The raw 4-bit quality-control value of a cell.
+///
+/// 品質管理情報(0-15)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct QualityFlag(u8);
+
+impl QualityFlag {
+    /// 4bitの品質管理情報を変換する。上位bitは無視する。
+    pub fn from_raw(raw: u16) -> Self {
+        Self((raw & 0x0F) as u8)
+    }
+
+    /// 元の4bitの値
+    pub fn to_raw(&self) -> u16 {
+        u16::from(self.0)
+    }
+
+    /// 値が`accepted`のどれかか。
+    pub fn is_any_of(&self, accepted: &[u16]) -> bool {
+        accepted.contains(&self.to_raw())
+    }
+}
+
+impl SecondaryMesh {
+    /// 品質管理情報が`pred`を満たすセルをtrueにした40x40配列
+    pub fn quality_mask<F>(&self, pred: F) -> Array2<bool>
+    where
+        F: Fn(QualityFlag) -> bool,
+    {
+        let cells = self.cells();
+        let side = self.cells_per_side();
+        Array2::from_shape_fn((side, side), |(row, col)| {
+            pred(cells[row * side + col].quality_flag())
+        })
+    }
+}
+
+impl PrimaryMesh {
+    /// 品質管理情報が`pred`を満たすセルをtrueにした(320, 320)配列。
+    /// データのない2次メッシュはfalse。
+    pub fn quality_mask<F>(&self, pred: F) -> Array2<bool>
+    where
+        F: Fn(QualityFlag) -> bool,
+    {
+        let side = self.cells_per_side();
+        let mut mask = Array2::from_elem((8 * side, 8 * side), false);
+        for mesh in self.secondary() {
+            let row = usize::from(7 - mesh.code().y()) * side;
+            let col = usize::from(mesh.code().x()) * side;
+            mask.slice_mut(s![row..row + side, col..col + side])
+                .assign(&mesh.quality_mask(&pred));
+        }
+        mask
+    }
+}
+
+impl XrainDataset {
+    /// 1次メッシュの品質管理情報が`pred`を満たすセルをtrueにした(320, 320)配列
+    pub fn primary_quality_mask<F>(&self, code: PrimaryMeshCode, pred: F) -> Option<Array2<bool>>
+    where
+        F: Fn(QualityFlag) -> bool,
+    {
+        self.primary(code).map(|p| p.quality_mask(pred))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+    use crate::XrainCell;
+
+    #[test]
+    fn test_from_raw() {
+        for raw in 0..16 {
+            assert_eq!(QualityFlag::from_raw(raw).to_raw(), raw);
+        }
+        assert_eq!(QualityFlag::from_raw(0x13), QualityFlag::from_raw(3));
+        assert!(QualityFlag::from_raw(1).is_any_of(&[0, 1]));
+        assert!(!QualityFlag::from_raw(3).is_any_of(&[0, 1]));
+    }
+
+    #[test]
+    fn test_mask() {
+        let mut cells = vec![XrainCell::new(0, 0); 1600];
+        cells[0] = XrainCell::new(3, 0);
+        let mesh = SecondaryMesh::new("543870".parse().unwrap(), cells).unwrap();
+        let mask = mesh.quality_mask(|q| q.is_any_of(&[0]));
+        assert!(!mask[[0, 0]]);
+        assert!(mask[[0, 1]]);
+
+        let dataset = XrainDataset::new(Fixture::default().header(), vec![mesh]).unwrap();
+        let mask = dataset
+            .primary_quality_mask("5438".parse().unwrap(), |q| q.to_raw() == 0)
+            .unwrap();
+        assert!(!mask[[0, 0]]);
+        assert!(mask[[0, 1]]);
+        assert_eq!(mask.iter().filter(|&&m| m).count(), 1599);
+    }
+}
@@ -0,0 +1,82 @@
+//! 雨量データ(12bit)の変換
+//!
+//! 分解能、有効な最大値、特殊値のコードは仕様書で未確認の暫定値。
+//! 実ファイルで確かめるまで、変換した雨量と特殊値の区別は目安として扱うこと。
+//! 元の12bitの値は`XrainCell::strength`で得られる。
+
+/// 雨量データの分解能(mm/h)。暫定値。
+pub const RAIN_RESOLUTION: f64 = 0.1;
+/// 雨量として有効な最大値(400.0mm/h)。暫定値。
+pub const RAIN_MAX_RAW: u16 = 4000;
+/// 未観測。暫定値。
+pub const RAW_NOT_OBSERVED: u16 = 0xFFD;
+/// 観測範囲外。暫定値。
+pub const RAW_OUT_OF_RANGE: u16 = 0xFFE;
+/// 欠測。暫定値。
+pub const RAW_MISSING: u16 = 0xFFF;
+
+/// Decoded rain intensity of one cell, using provisional scale and special codes.
+///
+/// 雨量データを物理量と特殊値に分けたもの。分解能と特殊値のコードは暫定値。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RainValue {
+    /// 雨量(mm/h)
+    Rain(f64),
+    /// 未観測
+    NotObserved,
+    /// 観測範囲外
+    OutOfRange,
+    /// 欠測
+    Missing,
+    /// 上記以外の範囲外の値(異常値)
+    Error(u16),
+}
+
+impl RainValue {
+    /// 12bitの雨量データを変換する。
+    pub fn from_raw(raw: u16) -> Self {
+        match raw {
+            0..=RAIN_MAX_RAW => RainValue::Rain(f64::from(raw) * RAIN_RESOLUTION),
+            RAW_NOT_OBSERVED => RainValue::NotObserved,
+            RAW_OUT_OF_RANGE => RainValue::OutOfRange,
+            RAW_MISSING => RainValue::Missing,
+            other => RainValue::Error(other),
+        }
+    }
+
+    /// 雨量(mm/h)。特殊値の場合は`None`。
+    pub fn mm_per_hour(&self) -> Option<f64> {
+        match self {
+            RainValue::Rain(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// 雨量(mm/h)。特殊値の場合はNaN。
+    pub fn to_f64(&self) -> f64 {
+        self.mm_per_hour().unwrap_or(f64::NAN)
+    }
+
+    /// 雨量として使える値か。
+    pub fn is_valid(&self) -> bool {
+        matches!(self, RainValue::Rain(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_raw() {
+        assert_eq!(RainValue::from_raw(0), RainValue::Rain(0.0));
+        assert_eq!(RainValue::from_raw(125).mm_per_hour(), Some(12.5));
+        assert_eq!(RainValue::from_raw(4000), RainValue::Rain(400.0));
+        assert_eq!(RainValue::from_raw(4001), RainValue::Error(4001));
+        assert_eq!(RainValue::from_raw(0xFFD), RainValue::NotObserved);
+        assert_eq!(RainValue::from_raw(0xFFE), RainValue::OutOfRange);
+        assert_eq!(RainValue::from_raw(0xFFF), RainValue::Missing);
+        assert!(RainValue::from_raw(0xFFF).to_f64().is_nan());
+        assert!(!RainValue::from_raw(0xFFE).is_valid());
+    }
+}
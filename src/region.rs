@@ -0,0 +1,227 @@
+//! 地整識別コードと対象エリア
+//!
+//! 地整の名前はファイル名の英字表記に使うので10地整すべてを持つ。
+//! 地整識別コードと合成範囲に対応しているのは実ファイルで確認できた関東だけで、
+//! ほかの地整は`owner_code`と`extent`が`None`になり、ヘッダーから地整を決められない。
+
+use std::fmt;
+
+use crate::{PrimaryMeshCode, XrainError, XrainHeader};
+
+/// Regional bureau (地方整備局) named in file names.
+/// Only Kanto has a known owner code and extent.
+///
+/// 地整識別
+/// 名前は10地整すべて、地整識別コードと合成範囲は関東だけ(`Region::supported`)。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Region {
+    Hokkaido,
+    Tohoku,
+    Kanto,
+    Hokuriku,
+    Chubu,
+    Kinki,
+    Chugoku,
+    Shikoku,
+    Kyushu,
+    Okinawa,
+}
+
+/// (地整, 地整識別コード, 南西端の1次メッシュ, 北東端の1次メッシュ)
+type RegionEntry = (Region, u8, (u8, u8), (u8, u8));
+
+/// 実ファイルのヘッダーで確認した地整。
+///
+/// 関東: KANTO00001-20191011-0000-G000-EL000000の地整識別(71)と南西端、北東端(4634-5543)。
+/// 他の地整は仕様書も実ファイルもないので載せない。確認できたらここに足す。
+const REGIONS: [RegionEntry; 1] = [(Region::Kanto, 71, (46, 34), (55, 43))];
+
+const ALL: [Region; 10] = [
+    Region::Hokkaido,
+    Region::Tohoku,
+    Region::Kanto,
+    Region::Hokuriku,
+    Region::Chubu,
+    Region::Kinki,
+    Region::Chugoku,
+    Region::Shikoku,
+    Region::Kyushu,
+    Region::Okinawa,
+];
+
+impl Region {
+    /// すべての地整(名前だけの地整を含む)
+    pub fn all() -> impl Iterator<Item = Region> {
+        ALL.into_iter()
+    }
+
+    /// 地整識別コードと合成範囲に対応している地整。現在は関東だけ。
+    pub fn supported() -> impl Iterator<Item = Region> {
+        REGIONS.into_iter().map(|r| r.0)
+    }
+
+    /// 地整識別コードと合成範囲に対応しているか。
+    pub fn is_supported(&self) -> bool {
+        self.entry().is_some()
+    }
+
+    /// 地整識別コードから変換する。対応している地整(関東)のコード以外は`None`。
+    pub fn from_owner_code(code: u8) -> Option<Self> {
+        REGIONS.iter().find(|r| r.1 == code).map(|r| r.0)
+    }
+
+    /// ファイル名の先頭の英字表記(例:KANTO)から変換する。
+    pub fn from_name_en(name: &str) -> Option<Self> {
+        Self::all().find(|r| r.name_en() == name)
+    }
+
+    /// 地整識別コード。対応していない地整(関東以外)は`None`。
+    pub fn owner_code(&self) -> Option<u8> {
+        self.entry().map(|r| r.1)
+    }
+
+    /// 地方名
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Hokkaido => "北海道",
+            Region::Tohoku => "東北",
+            Region::Kanto => "関東",
+            Region::Hokuriku => "北陸",
+            Region::Chubu => "中部",
+            Region::Kinki => "近畿",
+            Region::Chugoku => "中国",
+            Region::Shikoku => "四国",
+            Region::Kyushu => "九州",
+            Region::Okinawa => "沖縄",
+        }
+    }
+
+    /// ファイル名で使われる英字表記
+    pub fn name_en(&self) -> &'static str {
+        match self {
+            Region::Hokkaido => "HOKKAIDO",
+            Region::Tohoku => "TOHOKU",
+            Region::Kanto => "KANTO",
+            Region::Hokuriku => "HOKURIKU",
+            Region::Chubu => "CHUBU",
+            Region::Kinki => "KINKI",
+            Region::Chugoku => "CHUGOKU",
+            Region::Shikoku => "SHIKOKU",
+            Region::Kyushu => "KYUSHU",
+            Region::Okinawa => "OKINAWA",
+        }
+    }
+
+    /// 合成範囲の(南西端, 北東端)の1次メッシュ。対応していない地整(関東以外)は`None`。
+    pub fn extent(&self) -> Option<(PrimaryMeshCode, PrimaryMeshCode)> {
+        let (_, _, bl, tr) = self.entry()?;
+        Some((
+            PrimaryMeshCode::new(bl.0, bl.1).unwrap(),
+            PrimaryMeshCode::new(tr.0, tr.1).unwrap(),
+        ))
+    }
+
+    /// 1次メッシュが合成範囲に含まれるか。対応していない地整(関東以外)は`None`。
+    pub fn contains(&self, code: PrimaryMeshCode) -> Option<bool> {
+        let (bl, tr) = self.extent()?;
+        Some(
+            (bl.lat()..=tr.lat()).contains(&code.lat())
+                && (bl.lon()..=tr.lon()).contains(&code.lon()),
+        )
+    }
+
+    fn entry(&self) -> Option<RegionEntry> {
+        REGIONS.iter().find(|r| r.0 == *self).copied()
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name_en())
+    }
+}
+
+impl XrainHeader {
+    /// 地整識別コードの地整。対応している地整(関東)のコード以外は`None`。
+    pub fn region(&self) -> Option<Region> {
+        Region::from_owner_code(self.owner())
+    }
+
+    /// 地整識別コードが対応している地整のもので、南西端と北東端の1次メッシュがその地整の合成範囲に
+    /// 収まっているか確かめる。関東以外のコードは`XrainError::UnknownOwner`。
+    pub fn validate_region(&self) -> Result<Region, XrainError> {
+        let region = self.region().ok_or(XrainError::UnknownOwner {
+            found: self.owner(),
+        })?;
+        let inside = |code| region.contains(code) == Some(true);
+        if !inside(self.bottom_left()) || !inside(self.top_right()) {
+            return Err(XrainError::RegionExtentMismatch {
+                region,
+                bottom_left: self.bottom_left(),
+                top_right: self.top_right(),
+            });
+        }
+        Ok(region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservationTime;
+
+    #[test]
+    fn test_codes() {
+        for region in Region::all() {
+            assert_eq!(Region::from_name_en(region.name_en()), Some(region));
+            if let Some(code) = region.owner_code() {
+                assert_eq!(Region::from_owner_code(code), Some(region));
+            }
+            if let Some((bl, tr)) = region.extent() {
+                assert!(bl.lat() <= tr.lat() && bl.lon() <= tr.lon());
+            }
+        }
+        assert_eq!(Region::all().count(), 10);
+        assert_eq!(Region::supported().collect::<Vec<_>>(), [Region::Kanto]);
+        assert_eq!(
+            Region::all()
+                .filter(Region::is_supported)
+                .collect::<Vec<_>>(),
+            [Region::Kanto]
+        );
+        assert_eq!(Region::Kanto.owner_code(), Some(71));
+        assert_eq!(Region::Tohoku.owner_code(), None);
+        assert_eq!(Region::Tohoku.extent(), None);
+        assert_eq!(Region::from_owner_code(0), None);
+        assert_eq!(Region::from_owner_code(70), None);
+        assert_eq!(Region::Kanto.name(), "関東");
+    }
+
+    #[test]
+    fn test_validate() {
+        let builder = XrainHeader::builder()
+            .datetime(ObservationTime::new(2019, 10, 11, 0, 0, 0).unwrap())
+            .region(Region::Kanto);
+        let mut header = builder.build().unwrap();
+        assert_eq!(header.validate_region().unwrap(), Region::Kanto);
+
+        header = builder
+            .clone()
+            .extent("4634".parse().unwrap(), "6040".parse().unwrap())
+            .build()
+            .unwrap();
+        assert!(matches!(
+            header.validate_region(),
+            Err(XrainError::RegionExtentMismatch {
+                region: Region::Kanto,
+                ..
+            })
+        ));
+
+        header = builder.owner(0).build().unwrap();
+        assert!(matches!(
+            header.validate_region(),
+            Err(XrainError::UnknownOwner { found: 0 })
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+/// 16byte分のbit数
+const BIT_COUNT: usize = 128;
+
+/// The raw 16-byte system status of the header.
+///
+/// システムステータス
+/// サイトとbitの対応やbitの意味は仕様書で未確認なので、16byteをそのまま持つ。
+/// bitの番号は先頭バイトの最上位bitを0として数える。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SystemStatus {
+    bits: [u8; 16],
+}
+
+impl SystemStatus {
+    pub fn new(bits: [u8; 16]) -> Self {
+        Self { bits }
+    }
+
+    /// ヘッダーのシステムステータスそのまま
+    pub fn raw(&self) -> [u8; 16] {
+        self.bits
+    }
+
+    /// bit数(128)
+    pub fn bit_count(&self) -> usize {
+        BIT_COUNT
+    }
+
+    /// `index`番目のbitが1か。範囲外の場合は`None`。
+    pub fn bit(&self, index: usize) -> Option<bool> {
+        if index >= BIT_COUNT {
+            return None;
+        }
+        let byte = self.bits[index / 8];
+        Some(byte & (0x80 >> (index % 8)) != 0)
+    }
+
+    /// 1のbitの番号
+    pub fn set_bits(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..BIT_COUNT).filter(|&index| self.bit(index) == Some(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits() {
+        let mut bits = [0u8; 16];
+        bits[0] = 0b1010_0000;
+        bits[15] = 0b0000_0001;
+        let status = SystemStatus::new(bits);
+        assert_eq!(status.bit(0), Some(true));
+        assert_eq!(status.bit(1), Some(false));
+        assert_eq!(status.bit(128), None);
+        assert_eq!(status.set_bits().collect::<Vec<_>>(), vec![0, 2, 127]);
+        assert_eq!(status.raw(), bits);
+    }
+}
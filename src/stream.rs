@@ -0,0 +1,334 @@
+//! 少しずつ届くデータを読むパーサー
+//!
+//! 受け取ったバイト列を`feed`で渡し、`next_event`でヘッダーと2次メッシュを
+//! 揃ったものから順に取り出す。足りない場合は必要なバイト数を返す。
+
+use nom::Needed;
+
+use crate::view::BLOCK_HEADER_SIZE;
+use crate::{
+    read_block_header, read_header, read_single_block, SecondaryMesh, Section, XrainBlockHeader,
+    XrainError, XrainHeader, HEADER_SIZE,
+};
+
+/// What [`XrainStreamParser::next_event`] produced.
+///
+/// パーサーから取り出したもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// ヘッダーを読んだ。
+    Header(XrainHeader),
+    /// 2次メッシュを1つ読んだ。
+    Mesh(SecondaryMesh),
+    /// 次を読むにはさらにバイトが必要。
+    Incomplete(Needed),
+    /// ヘッダーのブロック数だけ読み終わった。
+    Done,
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Header,
+    BlockHeader,
+    Block {
+        /// ブロックヘッダーの位置
+        at: usize,
+        block: XrainBlockHeader,
+        /// 次に読むブロック内の2次メッシュの番号
+        next: u8,
+    },
+    Done,
+}
+
+/// Push-style parser for XRAIN data arriving in chunks.
+///
+/// 少しずつ届くXRAINファイルを読むパーサー
+#[derive(Debug, Clone)]
+pub struct XrainStreamParser {
+    /// 届いたバイト列。`start`より前は読み終わった部分。
+    buf: Vec<u8>,
+    /// `buf`のうち次に読む位置
+    start: usize,
+    /// `buf[start]`のファイル内の位置
+    offset: usize,
+    header: Option<XrainHeader>,
+    state: State,
+    /// 読み終わったブロック数
+    blocks_read: u16,
+}
+
+impl Default for XrainStreamParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XrainStreamParser {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            start: 0,
+            offset: 0,
+            header: None,
+            state: State::Header,
+            blocks_read: 0,
+        }
+    }
+
+    /// 届いたバイト列を追加する。
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.compact();
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// 読んだヘッダー
+    pub fn header(&self) -> Option<&XrainHeader> {
+        self.header.as_ref()
+    }
+
+    /// ファイルの先頭から読み終わったバイト数
+    pub fn position(&self) -> usize {
+        self.offset
+    }
+
+    /// まだ読んでいないバイト数
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// ヘッダーのブロック数だけ読み終わったか。
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// 次のヘッダーか2次メッシュを取り出す。
+    /// バイトが足りなければ`StreamEvent::Incomplete`を返すので、`feed`してから呼び直す。
+    /// エラーの`offset`はファイルの先頭からの位置。
+    pub fn next_event(&mut self) -> Result<StreamEvent, XrainError> {
+        loop {
+            match self.state.clone() {
+                State::Header => {
+                    if let Some(needed) = self.needed(HEADER_SIZE) {
+                        return Ok(StreamEvent::Incomplete(needed));
+                    }
+                    let (_, header) = read_header(self.rest()).map_err(|e| e.shift(self.offset))?;
+                    self.consume(HEADER_SIZE);
+                    self.state = if header.block_num() == 0 {
+                        State::Done
+                    } else {
+                        State::BlockHeader
+                    };
+                    self.header = Some(header.clone());
+                    return Ok(StreamEvent::Header(header));
+                }
+                State::BlockHeader => {
+                    if let Some(needed) = self.needed(BLOCK_HEADER_SIZE) {
+                        return Ok(StreamEvent::Incomplete(needed));
+                    }
+                    let at = self.offset;
+                    let (_, block) = read_block_header(self.rest()).map_err(|e| e.shift(at))?;
+                    if block.is_empty() {
+                        return Err(XrainError::EmptyBlock { offset: at });
+                    }
+                    self.consume(BLOCK_HEADER_SIZE);
+                    self.state = State::Block { at, block, next: 0 };
+                }
+                State::Block { at, block, next } => {
+                    let header = self.header.as_ref().expect("header is read first");
+                    let mesh_kind = header.mesh_kind();
+                    let block_num = header.block_num();
+                    if let Some(needed) = self.needed(mesh_kind.cells_per_mesh() * 2) {
+                        return Ok(StreamEvent::Incomplete(needed));
+                    }
+                    let code = block
+                        .first()
+                        .offset(0, i32::from(next))
+                        .ok_or(XrainError::InvalidMeshCode { offset: at })?;
+                    let (_, cells) = read_single_block(self.rest(), mesh_kind)
+                        .map_err(|e| e.shift(self.offset))?;
+                    self.consume(mesh_kind.cells_per_mesh() * 2);
+                    self.state = if next + 1 < block.len() {
+                        State::Block {
+                            at,
+                            block,
+                            next: next + 1,
+                        }
+                    } else {
+                        self.blocks_read += 1;
+                        if self.blocks_read == block_num {
+                            State::Done
+                        } else {
+                            State::BlockHeader
+                        }
+                    };
+                    return Ok(StreamEvent::Mesh(SecondaryMesh::from_cells(code, cells)));
+                }
+                State::Done => return Ok(StreamEvent::Done),
+            }
+        }
+    }
+
+    /// 入力が終わったときに呼ぶ。読み終わっていなければ`XrainError::Truncated`。
+    pub fn finish(&self) -> Result<(), XrainError> {
+        let (section, size) = match &self.state {
+            State::Done => return Ok(()),
+            State::Header => (Section::Header, HEADER_SIZE),
+            State::BlockHeader => (Section::BlockHeader, BLOCK_HEADER_SIZE),
+            State::Block { .. } => {
+                let header = self.header.as_ref().expect("header is read first");
+                (Section::Cell, header.mesh_kind().cells_per_mesh() * 2)
+            }
+        };
+        Err(XrainError::Truncated {
+            section,
+            offset: self.offset + self.buffered(),
+            needed: size.saturating_sub(self.buffered()).max(1),
+        })
+    }
+
+    /// `size`バイト揃っていなければ足りないバイト数
+    fn needed(&self, size: usize) -> Option<Needed> {
+        if self.buffered() >= size {
+            None
+        } else {
+            Some(Needed::new(size - self.buffered()))
+        }
+    }
+
+    /// まだ読んでいないバイト列
+    fn rest(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    /// 読み終わった`size`バイトを飛ばす。
+    /// 読み終わった部分が半分を超えたら詰める。
+    fn consume(&mut self, size: usize) {
+        self.start += size;
+        self.offset += size;
+        if self.start > self.buf.len() / 2 {
+            self.compact();
+        }
+    }
+
+    /// 読み終わった部分を捨てる。
+    fn compact(&mut self) {
+        self.buf.drain(..self.start);
+        self.start = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Corruption, Fixture};
+    use crate::XrainDataset;
+
+    /// `chunk`バイトずつ渡してすべてのイベントを集める。
+    fn run(data: &[u8], chunk: usize) -> Vec<StreamEvent> {
+        let mut parser = XrainStreamParser::new();
+        let mut events = Vec::new();
+        for part in data.chunks(chunk) {
+            parser.feed(part);
+            loop {
+                match parser.next_event().unwrap() {
+                    StreamEvent::Incomplete(_) => break,
+                    StreamEvent::Done => {
+                        events.push(StreamEvent::Done);
+                        break;
+                    }
+                    event => events.push(event),
+                }
+            }
+        }
+        parser.finish().unwrap();
+        events
+    }
+
+    #[test]
+    fn test_chunks() {
+        let data = Fixture::small().build();
+        let dataset = XrainDataset::from_bytes(&data).unwrap();
+        for chunk in [1, 7, 1000, data.len()] {
+            let events = run(&data, chunk);
+            assert_eq!(events.len(), 5, "chunk {}", chunk);
+            assert_eq!(events[0], StreamEvent::Header(dataset.header().clone()));
+            let meshes: Vec<SecondaryMesh> = events[1..4]
+                .iter()
+                .map(|e| match e {
+                    StreamEvent::Mesh(mesh) => mesh.clone(),
+                    other => panic!("{:?}", other),
+                })
+                .collect();
+            assert_eq!(
+                XrainDataset::new(dataset.header().clone(), meshes).unwrap(),
+                dataset
+            );
+            assert_eq!(events[4], StreamEvent::Done);
+        }
+    }
+
+    #[test]
+    fn test_single_feed() -> Result<(), XrainError> {
+        // 5438の南端から8行、80個の2次メッシュを一度に渡す。
+        let firsts: Vec<String> = (0..8).map(|y| format!("5438{}0", y)).collect();
+        let fixture = Fixture::default().blocks(firsts.iter().map(|c| (c.as_str(), 10)));
+        let data = fixture.build();
+        let mut parser = XrainStreamParser::new();
+        parser.feed(&data);
+        assert!(matches!(parser.next_event()?, StreamEvent::Header(_)));
+        let mut meshes = Vec::new();
+        loop {
+            match parser.next_event()? {
+                StreamEvent::Mesh(mesh) => meshes.push(mesh),
+                StreamEvent::Done => break,
+                other => panic!("{:?}", other),
+            }
+            assert_eq!(parser.buffered(), data.len() - parser.position());
+            assert!(parser.start <= parser.buf.len() / 2);
+        }
+        assert_eq!(meshes, fixture.meshes());
+        assert_eq!(parser.position(), data.len());
+        assert!(parser.buf.is_empty());
+        parser.finish()
+    }
+
+    #[test]
+    fn test_needed() {
+        let data = Fixture::small().build();
+        let mut parser = XrainStreamParser::new();
+        assert_eq!(
+            parser.next_event().unwrap(),
+            StreamEvent::Incomplete(Needed::new(64))
+        );
+        parser.feed(&data[..70]);
+        assert!(matches!(
+            parser.next_event().unwrap(),
+            StreamEvent::Header(_)
+        ));
+        assert_eq!(
+            parser.next_event().unwrap(),
+            StreamEvent::Incomplete(Needed::new(3198))
+        );
+        assert_eq!(parser.position(), 68);
+        assert!(matches!(
+            parser.finish(),
+            Err(XrainError::Truncated {
+                section: Section::Cell,
+                offset: 70,
+                needed: 3198
+            })
+        ));
+
+        let data = Fixture::default()
+            .blocks([("543870", 1)])
+            .corrupt(Corruption::EmptyBlock)
+            .build();
+        let mut parser = XrainStreamParser::new();
+        parser.feed(&data);
+        parser.next_event().unwrap();
+        assert!(matches!(
+            parser.next_event(),
+            Err(XrainError::EmptyBlock { offset: 64 })
+        ));
+    }
+}
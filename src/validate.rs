@@ -0,0 +1,256 @@
+//! ヘッダーの宣言とファイルの中身の整合性チェック
+//!
+//! パースできないファイルはエラーにし、パースはできるが不整合があるものは
+//! `ValidationReport`に問題点として集める。
+
+use std::fmt;
+
+use crate::view::BLOCK_HEADER_SIZE;
+use crate::{
+    read_block_header, read_header, BTreeMap, SecondaryMeshCode, Section, XrainError, XrainHeader,
+    XrainView, HEADER_SIZE,
+};
+
+/// One inconsistency found by [`validate`].
+///
+/// 見つかった不整合
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// ヘッダーのデータサイズと実際のバイト数が一致しない。
+    /// データサイズをファイル全体のバイト数とみなした暫定の検査。
+    DataSizeMismatch { declared: u32, actual: usize },
+    /// 宣言されたブロックを読み終えた後に余分なバイトがある。
+    TrailingBytes { offset: usize, len: usize },
+    /// 宣言されたブロック数の前に入力が終わった。
+    BlockCountMismatch { declared: u16, found: u16 },
+    /// 同じ2次メッシュが複数のブロックにある。offsetは後に出てきたセルの位置。
+    DuplicateMesh {
+        code: SecondaryMeshCode,
+        first: usize,
+        offset: usize,
+    },
+    /// 2次メッシュがヘッダーの南西端から北東端の範囲外。
+    OutsideExtent {
+        code: SecondaryMeshCode,
+        offset: usize,
+    },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::DataSizeMismatch { declared, actual } => write!(
+                f,
+                "header declares {} byte(s) but the data is {} byte(s)",
+                declared, actual
+            ),
+            ValidationIssue::TrailingBytes { offset, len } => {
+                write!(f, "{} trailing byte(s) at offset {}", len, offset)
+            }
+            ValidationIssue::BlockCountMismatch { declared, found } => write!(
+                f,
+                "header declares {} block(s) but {} were found",
+                declared, found
+            ),
+            ValidationIssue::DuplicateMesh {
+                code,
+                first,
+                offset,
+            } => write!(
+                f,
+                "mesh {} at offset {} duplicates the one at offset {}",
+                code, offset, first
+            ),
+            ValidationIssue::OutsideExtent { code, offset } => write!(
+                f,
+                "mesh {} at offset {} is outside the header extent",
+                code, offset
+            ),
+        }
+    }
+}
+
+/// Result of [`validate`].
+///
+/// 整合性チェックの結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    header: XrainHeader,
+    blocks: u16,
+    meshes: usize,
+    issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// ファイルのヘッダー
+    pub fn header(&self) -> &XrainHeader {
+        &self.header
+    }
+
+    /// 読めたブロック数
+    pub fn blocks(&self) -> u16 {
+        self.blocks
+    }
+
+    /// 読めた2次メッシュの数(重複を含む)
+    pub fn meshes(&self) -> usize {
+        self.meshes
+    }
+
+    /// 見つかった不整合(ファイル順)
+    pub fn issues(&self) -> &[ValidationIssue] {
+        &self.issues
+    }
+
+    /// 不整合がないか。
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// メモリ上のファイル1つ分の整合性を調べる。
+/// ヘッダーのデータサイズはヘッダーを含むファイル全体のバイト数とみなす(実ファイルでは未確認)。
+/// ヘッダーやブロックが壊れていて読めない場合はエラー。
+pub fn validate(xrain: &[u8]) -> Result<ValidationReport, XrainError> {
+    let (_, header) = read_header(xrain)?;
+    let mesh_size = header.mesh_kind().cells_per_mesh() * 2;
+    let mut issues = Vec::new();
+
+    if header.data_size() as usize != xrain.len() {
+        issues.push(ValidationIssue::DataSizeMismatch {
+            declared: header.data_size(),
+            actual: xrain.len(),
+        });
+    }
+
+    let mut seen: BTreeMap<SecondaryMeshCode, usize> = BTreeMap::new();
+    let mut meshes = 0;
+    let mut blocks = 0;
+    let mut pos = HEADER_SIZE;
+    while blocks < header.block_num() {
+        if pos >= xrain.len() {
+            issues.push(ValidationIssue::BlockCountMismatch {
+                declared: header.block_num(),
+                found: blocks,
+            });
+            break;
+        }
+        let (_, block) = read_block_header(&xrain[pos..]).map_err(|e| e.shift(pos))?;
+        if block.is_empty() {
+            return Err(XrainError::EmptyBlock { offset: pos });
+        }
+        let at = pos;
+        pos += BLOCK_HEADER_SIZE;
+        for code in block.meshes() {
+            let code = code.ok_or(XrainError::InvalidMeshCode { offset: at })?;
+            if pos + mesh_size > xrain.len() {
+                return Err(XrainError::Truncated {
+                    section: Section::Cell,
+                    offset: xrain.len(),
+                    needed: pos + mesh_size - xrain.len(),
+                });
+            }
+            if let Some(&first) = seen.get(&code) {
+                issues.push(ValidationIssue::DuplicateMesh {
+                    code,
+                    first,
+                    offset: pos,
+                });
+            } else {
+                seen.insert(code, pos);
+            }
+            if !header.covers(code.primary()) {
+                issues.push(ValidationIssue::OutsideExtent { code, offset: pos });
+            }
+            meshes += 1;
+            pos += mesh_size;
+        }
+        blocks += 1;
+    }
+
+    if pos < xrain.len() {
+        issues.push(ValidationIssue::TrailingBytes {
+            offset: pos,
+            len: xrain.len() - pos,
+        });
+    }
+
+    Ok(ValidationReport {
+        header,
+        blocks,
+        meshes,
+        issues,
+    })
+}
+
+impl<B: AsRef<[u8]>> XrainView<B> {
+    /// ファイルの整合性を調べる。
+    pub fn validate(&self) -> Result<ValidationReport, XrainError> {
+        validate(self.bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Corruption, Fixture};
+
+    #[test]
+    fn test_valid() -> Result<(), XrainError> {
+        let data = Fixture::small().build();
+        let report = validate(&data)?;
+        assert!(report.is_valid(), "{:?}", report.issues());
+        assert_eq!(report.blocks(), 2);
+        assert_eq!(report.meshes(), 3);
+        assert_eq!(XrainView::new(data.as_slice())?.validate()?, report);
+        Ok(())
+    }
+
+    #[test]
+    fn test_issues() -> Result<(), XrainError> {
+        // 2ブロック目に同じメッシュと範囲外のメッシュ、最後に余分なバイト
+        let data = Fixture::default()
+            .blocks([("543870", 2), ("543870", 1), ("604000", 1)])
+            .corrupt(Corruption::TrailingBytes(3))
+            .build();
+
+        let report = validate(&data)?;
+        let end = 64 + 4 + 6400 + 4 + 3200 + 4 + 3200;
+        assert_eq!(
+            report.issues(),
+            &[
+                ValidationIssue::DataSizeMismatch {
+                    declared: end as u32,
+                    actual: end + 3,
+                },
+                ValidationIssue::DuplicateMesh {
+                    code: "543870".parse().unwrap(),
+                    first: 68,
+                    offset: 64 + 4 + 6400 + 4,
+                },
+                ValidationIssue::OutsideExtent {
+                    code: "604000".parse().unwrap(),
+                    offset: end - 3200,
+                },
+                ValidationIssue::TrailingBytes {
+                    offset: end,
+                    len: 3
+                },
+            ]
+        );
+
+        let report = validate(
+            &Fixture::small()
+                .corrupt(Corruption::MissingBlocks(1))
+                .build(),
+        )?;
+        assert_eq!(
+            report.issues(),
+            &[ValidationIssue::BlockCountMismatch {
+                declared: 3,
+                found: 2
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+//! メモリマップしたファイルを必要な2次メッシュだけ読むリーダー
+//!
+//! 最初にブロックヘッダーだけを読んでブロックの位置を記録し、
+//! 2次メッシュのセルはアクセスされたときにデコードする。
+
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::{
+    read_block_header, read_cell, read_header, read_single_block, MeshIndex,
+    QuarterTertiaryMeshCode, SecondaryMesh, SecondaryMeshCode, Section, XrainBlockHeader,
+    XrainCell, XrainDataset, XrainError, XrainHeader, HEADER_SIZE,
+};
+
+/// ブロックヘッダーのバイト数
+pub(crate) const BLOCK_HEADER_SIZE: usize = 4;
+
+/// Location of one block in the file.
+///
+/// ブロックの位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockEntry {
+    offset: usize,
+    header: XrainBlockHeader,
+}
+
+impl BlockEntry {
+    /// 先頭の2次メッシュのセルの位置(ファイルの先頭からのバイト数)
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// ブロックヘッダー
+    pub fn header(&self) -> XrainBlockHeader {
+        self.header
+    }
+}
+
+/// A lazily decoded XRAIN file over any byte buffer, such as a memory map.
+///
+/// バイト列の上のXRAINファイル。2次メッシュはアクセスしたときにデコードする。
+#[derive(Debug)]
+pub struct XrainView<B: AsRef<[u8]>> {
+    bytes: B,
+    blocks: Vec<BlockEntry>,
+    index: MeshIndex,
+}
+
+/// メモリマップしたファイル
+pub type MappedXrain = XrainView<Mmap>;
+
+impl XrainView<Mmap> {
+    /// ファイルをメモリマップして開く。
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, XrainError> {
+        let file = File::open(path)?;
+        // SAFETY: 読み込み専用でマップする。開いている間に他のプロセスがファイルを書き換えた場合の
+        // 内容は保証されないが、範囲はnewで検査するので境界外を読むことはない。
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::new(mmap)
+    }
+}
+
+impl<B: AsRef<[u8]>> XrainView<B> {
+    /// ヘッダーとすべてのブロックヘッダーを読み、各ブロックのセルが揃っているか確かめる。
+    pub fn new(bytes: B) -> Result<Self, XrainError> {
+        let data = bytes.as_ref();
+        let (_, header) = read_header(data)?;
+        let mesh_size = header.mesh_kind().cells_per_mesh() * 2;
+
+        let mut blocks = Vec::with_capacity(usize::from(header.block_num()));
+        let mut index = MeshIndex::new(header.clone());
+        let mut pos = HEADER_SIZE;
+        for i in 0..header.block_num() {
+            if pos >= data.len() {
+                return Err(XrainError::BlockCountMismatch {
+                    declared: header.block_num(),
+                    found: i,
+                });
+            }
+            let (_, block) = read_block_header(&data[pos..]).map_err(|e| e.shift(pos))?;
+            if block.is_empty() {
+                return Err(XrainError::EmptyBlock { offset: pos });
+            }
+            let offset = pos + BLOCK_HEADER_SIZE;
+            let end = offset + usize::from(block.len()) * mesh_size;
+            if end > data.len() {
+                return Err(XrainError::Truncated {
+                    section: Section::Cell,
+                    offset: data.len(),
+                    needed: end - data.len(),
+                });
+            }
+            index.insert_block(&block, offset, pos)?;
+            blocks.push(BlockEntry {
+                offset,
+                header: block,
+            });
+            pos = end;
+        }
+        Ok(Self {
+            bytes,
+            blocks,
+            index,
+        })
+    }
+
+    /// ファイルのヘッダー
+    pub fn header(&self) -> &XrainHeader {
+        self.index.header()
+    }
+
+    /// ファイル全体のバイト列
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// 2次メッシュコードからの索引
+    pub fn index(&self) -> &MeshIndex {
+        &self.index
+    }
+
+    /// すべてのブロック(ファイル順)
+    pub fn blocks(&self) -> &[BlockEntry] {
+        &self.blocks
+    }
+
+    /// 2次メッシュの数
+    pub fn len(&self) -> usize {
+        self.blocks
+            .iter()
+            .map(|b| usize::from(b.header.len()))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// 含まれる2次メッシュコードとセルの位置(ファイル順)
+    pub fn mesh_offsets(&self) -> impl Iterator<Item = (SecondaryMeshCode, usize)> + '_ {
+        let mesh_size = self.mesh_size();
+        self.blocks.iter().flat_map(move |block| {
+            block
+                .header
+                .meshes()
+                .enumerate()
+                .filter_map(move |(i, code)| Some((code?, block.offset + i * mesh_size)))
+        })
+    }
+
+    /// 2次メッシュのセルの位置
+    pub fn mesh_offset(&self, code: SecondaryMeshCode) -> Option<usize> {
+        self.index.offset(code)
+    }
+
+    /// 2次メッシュをデコードする。含まれなければ`None`。
+    pub fn secondary(&self, code: SecondaryMeshCode) -> Option<SecondaryMesh> {
+        self.mesh_offset(code)
+            .map(|offset| self.decode(code, offset))
+    }
+
+    /// 2次メッシュを1つずつデコードする(ファイル順)。
+    pub fn meshes(&self) -> impl Iterator<Item = SecondaryMesh> + '_ {
+        self.mesh_offsets()
+            .map(move |(code, offset)| self.decode(code, offset))
+    }
+
+    /// ブロック内の2次メッシュをデコードする。
+    pub fn block_meshes<'a>(
+        &'a self,
+        block: &'a BlockEntry,
+    ) -> impl Iterator<Item = SecondaryMesh> + 'a {
+        let mesh_size = self.mesh_size();
+        block
+            .header
+            .meshes()
+            .enumerate()
+            .filter_map(move |(i, code)| Some(self.decode(code?, block.offset + i * mesh_size)))
+    }
+
+    /// 1セルだけを読む。
+    pub fn cell(&self, code: QuarterTertiaryMeshCode) -> Option<XrainCell> {
+        let index = self.header().mesh_kind().cell_index(code);
+        let offset = self.mesh_offset(code.secondary())? + index * 2;
+        read_cell(&self.bytes.as_ref()[offset..])
+            .ok()
+            .map(|(_, cell)| cell)
+    }
+
+    /// すべての2次メッシュをデコードする。
+    pub fn to_dataset(&self) -> XrainDataset {
+        XrainDataset::new(self.header().clone(), self.meshes())
+            .expect("decoded meshes have the header's cell count")
+            .with_blocks(self.blocks.iter().map(|b| b.header).collect())
+    }
+
+    fn mesh_size(&self) -> usize {
+        self.index.mesh_size()
+    }
+
+    /// 範囲はnewで確かめてあるのでデコードは失敗しない。
+    fn decode(&self, code: SecondaryMeshCode, offset: usize) -> SecondaryMesh {
+        let data = &self.bytes.as_ref()[offset..offset + self.mesh_size()];
+        let (_, cells) = read_single_block(data, self.header().mesh_kind())
+            .expect("mesh range is checked when the view is built");
+        SecondaryMesh::from_cells(code, cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{code, Corruption, Fixture, Pattern};
+
+    /// 543870から2つ、533900から1つの2次メッシュを持つファイル
+    #[test]
+    fn test_lazy() -> Result<(), XrainError> {
+        let data = Fixture::small().build();
+        let view = XrainView::new(data.as_slice())?;
+        assert_eq!(view.blocks().len(), 2);
+        assert_eq!(view.blocks()[1].offset(), 64 + 4 + 6400 + 4);
+        assert_eq!(view.len(), 3);
+
+        let mesh = view.secondary(code("543871")).unwrap();
+        assert_eq!(
+            mesh.cell(0, 0),
+            Some(&Pattern::Gradient.cell(code("543871"), 0))
+        );
+        assert!(view.secondary(code("543872")).is_none());
+        assert_eq!(
+            view.cell(code("533900").cell(5, 5).unwrap()),
+            Some(Pattern::Gradient.cell(code("533900"), 5 * 40 + 5))
+        );
+        assert_eq!(view.to_dataset(), XrainDataset::from_bytes(&data)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            XrainView::new(Fixture::small().corrupt(Corruption::Truncate(1)).build()),
+            Err(XrainError::Truncated {
+                section: Section::Cell,
+                needed: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            XrainView::new(
+                Fixture::small()
+                    .corrupt(Corruption::Truncate(4 + 3200))
+                    .build()
+            ),
+            Err(XrainError::BlockCountMismatch {
+                declared: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_mmap() -> Result<(), XrainError> {
+        let path = Fixture::small().write("xrain-view")?;
+        let view = MappedXrain::open(&path)?;
+        let count = view.meshes().count();
+        drop(view);
+        std::fs::remove_file(&path)?;
+        assert_eq!(count, 3);
+        Ok(())
+    }
+}
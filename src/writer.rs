@@ -0,0 +1,625 @@
+//! XRAINファイルの書き出し
+//!
+//! `read_header`、`read_block_header`、`read_cell`の逆変換。
+//! 2次メッシュを東へ連続するものごとにブロックにまとめ、ヘッダーのブロック数とデータサイズを埋める。
+//! データサイズはヘッダーを含むファイル全体のバイト数とみなしているが、実ファイルでは未確認。
+//! 読んだファイルをそのまま書き出すときは読んだ値を書く。
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::view::BLOCK_HEADER_SIZE;
+use crate::{
+    to_bcd, DataKind, ObservationTime, PrimaryMeshCode, Region, SecondaryMesh, SystemStatus,
+    XrainBlockHeader, XrainCell, XrainDataset, XrainError, XrainHeader, XrainView, HEADER_SIZE,
+};
+
+/// Validated constructor for [`XrainHeader`].
+///
+/// ヘッダーを組み立てる。地整識別コード、データ種別3、観測日時、南西端と北東端の1次メッシュは必須。
+/// ブロック数とデータサイズは`XrainEncoder`が中身から埋める。
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct XrainHeaderBuilder {
+    owner: Option<u8>,
+    datetime: Option<ObservationTime>,
+    extent: Option<(PrimaryMeshCode, PrimaryMeshCode)>,
+    data_kind: DataKind,
+    data_kind3: Option<u16>,
+    system_status: SystemStatus,
+    device_no: u8,
+    response_status: u8,
+}
+
+impl XrainHeader {
+    /// ヘッダーを組み立てる。
+    pub fn builder() -> XrainHeaderBuilder {
+        XrainHeaderBuilder::default()
+    }
+}
+
+impl XrainHeaderBuilder {
+    pub fn owner(mut self, owner: u8) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    /// 地整識別コードと合成範囲を地整のものにする。対応していない地整(関東以外)では何も設定しない。
+    /// データ種別3は実ファイルと同じく地整識別コードにする。
+    pub fn region(mut self, region: Region) -> Self {
+        self.owner = region.owner_code().or(self.owner);
+        self.data_kind3 = region.owner_code().map(u16::from).or(self.data_kind3);
+        self.extent = region.extent().or(self.extent);
+        self
+    }
+
+    pub fn datetime(mut self, datetime: ObservationTime) -> Self {
+        self.datetime = Some(datetime);
+        self
+    }
+
+    /// 南西端と北東端の1次メッシュ
+    pub fn extent(mut self, bottom_left: PrimaryMeshCode, top_right: PrimaryMeshCode) -> Self {
+        self.extent = Some((bottom_left, top_right));
+        self
+    }
+
+    pub fn data_kind(mut self, data_kind: DataKind) -> Self {
+        self.data_kind = data_kind;
+        self
+    }
+
+    /// データ種別3の値。意味が分からないので、読んだファイルの値か実ファイルと同じ値を渡す。
+    pub fn data_kind3(mut self, data_kind3: u16) -> Self {
+        self.data_kind3 = Some(data_kind3);
+        self
+    }
+
+    pub fn system_status(mut self, system_status: SystemStatus) -> Self {
+        self.system_status = system_status;
+        self
+    }
+
+    pub fn device_no(mut self, device_no: u8) -> Self {
+        self.device_no = device_no;
+        self
+    }
+
+    pub fn response_status(mut self, response_status: u8) -> Self {
+        self.response_status = response_status;
+        self
+    }
+
+    /// 必須の項目がなければ`XrainError::MissingHeaderField`、
+    /// 南西端が北東端より北か東なら`XrainError::InvalidExtent`。
+    pub fn build(&self) -> Result<XrainHeader, XrainError> {
+        let missing = |field| XrainError::MissingHeaderField { field };
+        let owner = self.owner.ok_or(missing("owner"))?;
+        let data_kind3 = self.data_kind3.ok_or(missing("data_kind3"))?;
+        let datetime = self.datetime.ok_or(missing("datetime"))?;
+        let (bottom_left, top_right) = self.extent.ok_or(missing("extent"))?;
+        if bottom_left.lat() > top_right.lat() || bottom_left.lon() > top_right.lon() {
+            return Err(XrainError::InvalidExtent {
+                bottom_left,
+                top_right,
+            });
+        }
+        Ok(XrainHeader {
+            owner,
+            data_kind: self.data_kind,
+            data_kind3,
+            datetime,
+            system_status: self.system_status,
+            device_no: self.device_no,
+            response_status: self.response_status,
+            bottom_left,
+            top_right,
+            ..XrainHeader::blank()
+        })
+    }
+}
+
+/// ヘッダーを64byteで書く。予備領域はヘッダーの値のまま。
+pub fn write_header<W: Write>(writer: &mut W, header: &XrainHeader) -> Result<(), XrainError> {
+    let mut bytes = [0u8; HEADER_SIZE];
+    bytes[0] = 0xFD;
+    bytes[1] = header.owner;
+    bytes[2] = DataKind::KIND1;
+    bytes[3] = header.data_kind.to_raw();
+    bytes[4..6].copy_from_slice(&header.data_kind3.to_be_bytes());
+    //ヘッダ種別、観測値識別
+    bytes[6] = 0x01;
+    bytes[7] = 0x05;
+    bytes[8..24].copy_from_slice(&header.datetime.to_bcd());
+    bytes[15..24].copy_from_slice(&header.datetime_reserved);
+    bytes[24..40].copy_from_slice(&header.system_status.raw());
+    bytes[40] = header.device_no;
+    bytes[41] = header.response_status;
+    bytes[42..44].copy_from_slice(&header.block_num.to_be_bytes());
+    bytes[44..48].copy_from_slice(&header.data_size.to_be_bytes());
+    bytes[48] = to_bcd(header.bottom_left.lat());
+    bytes[49] = to_bcd(header.bottom_left.lon());
+    bytes[50] = to_bcd(header.top_right.lat());
+    bytes[51] = to_bcd(header.top_right.lon());
+    bytes[52..62].copy_from_slice(&header.reserved);
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// ブロックヘッダーを4byteで書く。
+pub fn write_block_header<W: Write>(
+    writer: &mut W,
+    block: &XrainBlockHeader,
+) -> Result<(), XrainError> {
+    let first = block.first();
+    let bytes = [
+        first.primary().lat(),
+        first.primary().lon(),
+        (first.y() << 4) | first.x(),
+        block.len(),
+    ];
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// セルを上位4bitが品質、下位12bitが雨量の2byteで書く。収まらない値は`None`。
+fn pack_cell(cell: &XrainCell) -> Option<[u8; 2]> {
+    if cell.quality() > 0x0F || cell.strength() > 0x0FFF {
+        return None;
+    }
+    Some(((cell.quality() << 12) | cell.strength()).to_be_bytes())
+}
+
+/// 2次メッシュのすべてのセルを北西から順に書く。
+/// 範囲外のセルがあれば何も書かずに`XrainError::CellOutOfRange`。
+pub fn write_single_block<W: Write>(
+    writer: &mut W,
+    mesh: &SecondaryMesh,
+) -> Result<(), XrainError> {
+    let mut bytes = Vec::with_capacity(mesh.cells().len() * 2);
+    for (index, cell) in mesh.cells().iter().enumerate() {
+        let packed = pack_cell(cell).ok_or(XrainError::CellOutOfRange {
+            code: mesh.code(),
+            index,
+        })?;
+        bytes.extend_from_slice(&packed);
+    }
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Builds an XRAIN file from a header and secondary meshes.
+///
+/// XRAINファイルを組み立てる。
+/// 2次メッシュは追加した順に書き、ヘッダーのブロック数は中身から求める。
+/// データサイズは読んだファイルと同じ構成なら読んだ値、そうでなければ中身から求める。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XrainEncoder {
+    header: XrainHeader,
+    blocks: Vec<Vec<SecondaryMesh>>,
+    /// 読んだファイルのデータサイズ。2次メッシュを追加すると捨てる。
+    data_size: Option<u32>,
+}
+
+impl XrainEncoder {
+    /// ブロックのない状態で作成する。ヘッダーのブロック数とデータサイズは使わない。
+    pub fn new(header: XrainHeader) -> Self {
+        Self {
+            header,
+            blocks: Vec::new(),
+            data_size: None,
+        }
+    }
+
+    /// ファイルから読んだデータセットは、読んだときのブロック構成で並べ、読んだデータサイズを書く。
+    /// 2次メッシュから作成したデータセットは南の行から、各行は西から順に並べ、
+    /// 東隣の2次メッシュは1次メッシュの境界をまたいでも同じブロックにまとめる。
+    pub fn from_dataset(dataset: &XrainDataset) -> Result<Self, XrainError> {
+        if let Some(blocks) = Self::file_blocks(dataset) {
+            let mut encoder = Self::new(dataset.header().clone());
+            for block in blocks {
+                encoder.push_block(block)?;
+            }
+            encoder.data_size = Some(dataset.header().data_size());
+            return Ok(encoder);
+        }
+        let mut meshes: Vec<&SecondaryMesh> = dataset.secondary_meshes().collect();
+        meshes.sort_by_key(|mesh| {
+            let code = mesh.code();
+            (
+                u16::from(code.primary().lat()) * 8 + u16::from(code.y()),
+                u16::from(code.primary().lon()) * 8 + u16::from(code.x()),
+            )
+        });
+        let mut encoder = Self::new(dataset.header().clone());
+        encoder.extend(meshes.into_iter().cloned())?;
+        Ok(encoder)
+    }
+
+    /// ファイルと同じブロック構成で作成する。データサイズは読んだ値を書くので、バイト単位で元と一致する。
+    pub fn from_view<B: AsRef<[u8]>>(view: &XrainView<B>) -> Self {
+        Self {
+            header: view.header().clone(),
+            blocks: view
+                .blocks()
+                .iter()
+                .map(|block| view.block_meshes(block).collect())
+                .collect(),
+            data_size: Some(view.header().data_size()),
+        }
+    }
+
+    /// 読んだデータサイズを捨て、ヘッダーを含むファイル全体のバイト数を書くようにする。
+    pub fn recompute_data_size(mut self) -> Self {
+        self.data_size = None;
+        self
+    }
+
+    /// 2次メッシュを追加する。直前の2次メッシュの東隣で、ブロックが255個未満ならそのブロックに続け、
+    /// そうでなければ新しいブロックを始める。
+    pub fn push(&mut self, mesh: SecondaryMesh) -> Result<(), XrainError> {
+        self.check(&mesh)?;
+        let continues = self
+            .blocks
+            .last()
+            .is_some_and(|block| Self::continues(block, &mesh));
+        if continues {
+            self.check_size(0, 1)?;
+            self.blocks.last_mut().expect("checked above").push(mesh);
+            self.data_size = None;
+            return Ok(());
+        }
+        self.check_size(1, 1)?;
+        self.blocks.push(vec![mesh]);
+        self.data_size = None;
+        Ok(())
+    }
+
+    /// 2次メッシュを順に`push`する。
+    pub fn extend<I>(&mut self, meshes: I) -> Result<(), XrainError>
+    where
+        I: IntoIterator<Item = SecondaryMesh>,
+    {
+        for mesh in meshes {
+            self.push(mesh)?;
+        }
+        Ok(())
+    }
+
+    /// 1つのブロックとして追加する。直前のブロックとはまとめない。
+    /// 東へ連続していないか255個を超える場合は`XrainError::InvalidBlock`。
+    pub fn push_block<I>(&mut self, meshes: I) -> Result<(), XrainError>
+    where
+        I: IntoIterator<Item = SecondaryMesh>,
+    {
+        let mut block: Vec<SecondaryMesh> = Vec::new();
+        for mesh in meshes {
+            self.check(&mesh)?;
+            if !block.is_empty() && !Self::continues(&block, &mesh) {
+                return Err(XrainError::InvalidBlock { code: mesh.code() });
+            }
+            block.push(mesh);
+        }
+        if block.is_empty() {
+            return Err(XrainError::EmptyBlock {
+                offset: self.byte_len(),
+            });
+        }
+        self.check_size(1, block.len())?;
+        self.blocks.push(block);
+        self.data_size = None;
+        Ok(())
+    }
+
+    /// ブロック数
+    pub fn block_num(&self) -> u16 {
+        // check_sizeで範囲内に収めている。
+        self.blocks.len() as u16
+    }
+
+    /// 書き出すバイト数(ヘッダーを含む)
+    pub fn byte_len(&self) -> usize {
+        let meshes: usize = self.blocks.iter().map(|b| b.len()).sum();
+        HEADER_SIZE + self.blocks.len() * BLOCK_HEADER_SIZE + meshes * self.mesh_size()
+    }
+
+    /// ヘッダーに書くデータサイズ。読んだ値がなければ`byte_len`。
+    pub fn data_size(&self) -> u32 {
+        // check_sizeでu32に収めている。
+        self.data_size.unwrap_or(self.byte_len() as u32)
+    }
+
+    /// ブロック数とデータサイズを埋めたヘッダー
+    pub fn header(&self) -> XrainHeader {
+        let mut header = self.header.clone();
+        header.block_num = self.block_num();
+        header.data_size = self.data_size();
+        header
+    }
+
+    /// `writer`に書き出す。
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), XrainError> {
+        write_header(writer, &self.header())?;
+        for block in &self.blocks {
+            write_block_header(
+                writer,
+                &XrainBlockHeader::new(block[0].code(), block.len() as u8),
+            )?;
+            for mesh in block {
+                write_single_block(writer, mesh)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// メモリ上に書き出す。
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.byte_len());
+        self.write_to(&mut bytes)
+            .expect("cells are checked when they are pushed and Vec does not fail");
+        bytes
+    }
+
+    /// ファイルに書き出す。
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), XrainError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn mesh_size(&self) -> usize {
+        self.header.mesh_kind().cells_per_mesh() * 2
+    }
+
+    /// データセットのブロック構成どおりに並べた2次メッシュ。
+    /// ブロック構成がないか、ブロックの2次メッシュとデータセットの2次メッシュが一致しなければ`None`。
+    fn file_blocks(dataset: &XrainDataset) -> Option<Vec<Vec<SecondaryMesh>>> {
+        if dataset.blocks().is_empty() {
+            return None;
+        }
+        let blocks = dataset
+            .blocks()
+            .iter()
+            .map(|block| {
+                block
+                    .meshes()
+                    .map(|code| dataset.secondary(code?).cloned())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let count: usize = blocks.iter().map(Vec::len).sum();
+        (count == dataset.len()).then_some(blocks)
+    }
+
+    /// セル数とセルの値が書ける範囲か。
+    fn check(&self, mesh: &SecondaryMesh) -> Result<(), XrainError> {
+        let expected = self.header.mesh_kind().cells_per_mesh();
+        if mesh.cells().len() != expected {
+            return Err(XrainError::CellCountMismatch {
+                code: mesh.code(),
+                expected,
+                found: mesh.cells().len(),
+            });
+        }
+        match mesh
+            .cells()
+            .iter()
+            .position(|cell| pack_cell(cell).is_none())
+        {
+            Some(index) => Err(XrainError::CellOutOfRange {
+                code: mesh.code(),
+                index,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// `blocks`個のブロックと`meshes`個の2次メッシュを足してもヘッダーに書けるか。
+    fn check_size(&self, blocks: usize, meshes: usize) -> Result<(), XrainError> {
+        let size = self.byte_len() + blocks * BLOCK_HEADER_SIZE + meshes * self.mesh_size();
+        if self.blocks.len() + blocks > usize::from(u16::MAX) || size > u32::MAX as usize {
+            return Err(XrainError::TooLarge);
+        }
+        Ok(())
+    }
+
+    /// ブロックの末尾の東隣で、ブロックにまだ入るか。
+    fn continues(block: &[SecondaryMesh], mesh: &SecondaryMesh) -> bool {
+        block.len() < usize::from(u8::MAX)
+            && block
+                .last()
+                .and_then(|last| last.code().offset(0, 1))
+                .is_some_and(|next| next == mesh.code())
+    }
+}
+
+impl XrainDataset {
+    /// XRAINファイルのバイト列に書き出す。2次メッシュの並びとデータサイズは`XrainEncoder::from_dataset`。
+    /// 読んだファイルの2次メッシュを増減していなければ、バイト単位で元と一致する。
+    pub fn to_bytes(&self) -> Result<Vec<u8>, XrainError> {
+        Ok(XrainEncoder::from_dataset(self)?.to_bytes())
+    }
+
+    /// XRAINファイルとして保存する。
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), XrainError> {
+        XrainEncoder::from_dataset(self)?.save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+    use crate::read_header;
+
+    #[test]
+    fn test_round_trip() -> Result<(), XrainError> {
+        // 543877の東隣は1次メッシュをまたいだ543970
+        let data = Fixture::default()
+            .blocks([("533900", 1), ("543877", 2)])
+            .build();
+        let dataset = XrainDataset::from_bytes(&data)?;
+        assert_eq!(dataset.to_bytes()?, data);
+        assert_eq!(XrainDataset::from_bytes(&dataset.to_bytes()?)?, dataset);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_layout() -> Result<(), XrainError> {
+        // ブロックが並べ替えの順でなく、予備領域が0でなく、データサイズがヘッダーを含まないファイル
+        let mut data = Fixture::default()
+            .blocks([("543870", 2), ("533900", 1), ("543802", 2), ("543800", 2)])
+            .build();
+        data[15..24].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let body = (data.len() - HEADER_SIZE) as u32;
+        data[44..48].copy_from_slice(&body.to_be_bytes());
+        data[52..62].copy_from_slice(&[0xA0; 10]);
+
+        let dataset = XrainDataset::from_bytes(&data)?;
+        assert_eq!(dataset.header().reserved(), [0xA0; 10]);
+        assert_eq!(dataset.header().datetime_reserved()[0], 1);
+        let firsts: Vec<String> = dataset
+            .blocks()
+            .iter()
+            .map(|b| b.first().to_string())
+            .collect();
+        assert_eq!(firsts, ["543870", "533900", "543802", "543800"]);
+        assert_eq!(dataset.to_bytes()?, data);
+
+        let view = XrainView::new(data.as_slice())?;
+        assert_eq!(view.to_dataset().to_bytes()?, data);
+        assert_eq!(XrainEncoder::from_view(&view).to_bytes(), data);
+        let recomputed = XrainEncoder::from_view(&view).recompute_data_size();
+        assert_eq!(recomputed.header().data_size() as usize, data.len());
+
+        // 2次メッシュから作成したデータセットは並べ替えてまとめる
+        let rebuilt = XrainDataset::new(
+            dataset.header().clone(),
+            dataset.secondary_meshes().cloned(),
+        )?;
+        let bytes = rebuilt.to_bytes()?;
+        assert_eq!(read_header(&bytes)?.1.data_size() as usize, bytes.len());
+        assert!(XrainDataset::from_bytes(&bytes)?
+            .secondary_meshes()
+            .eq(dataset.secondary_meshes()));
+        assert_eq!(XrainView::new(bytes.as_slice())?.blocks().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_builder() -> Result<(), XrainError> {
+        let datetime = ObservationTime::new(2019, 10, 11, 0, 5, 0).unwrap();
+        let mut status = [0u8; 16];
+        status[1] = 0x80;
+        let header = XrainHeader::builder()
+            .region(Region::Kanto)
+            .datetime(datetime)
+            .system_status(SystemStatus::new(status))
+            .build()?;
+        let mesh = SecondaryMesh::new(
+            "543870".parse().unwrap(),
+            (0..1600).map(|i| XrainCell::new(1, i)).collect(),
+        )?;
+        let mut encoder = XrainEncoder::new(header);
+        encoder.push(mesh.clone())?;
+        // ファイルに書けるのは250mメッシュだけ
+        assert!(encoder
+            .push(SecondaryMesh::new(
+                "543871".parse().unwrap(),
+                vec![XrainCell::default(); 400]
+            )?)
+            .is_err());
+
+        let dataset = crate::read_xrain(&encoder.to_bytes())?;
+        let header = dataset.header();
+        assert_eq!(header.owner(), 71);
+        assert_eq!(header.region(), Some(Region::Kanto));
+        assert_eq!(header.datetime(), datetime);
+        assert_eq!(header.data_kind3(), 0x0047);
+        assert_eq!(header.system_status().set_bits().collect::<Vec<_>>(), [8]);
+        assert_eq!(header.bottom_left(), "4634".parse().unwrap());
+        assert_eq!(header.top_right(), "5543".parse().unwrap());
+        assert_eq!(header.block_num(), 1);
+        assert_eq!(dataset.secondary(mesh.code()), Some(&mesh));
+
+        assert!(matches!(
+            XrainHeader::builder().region(Region::Kanto).build(),
+            Err(XrainError::MissingHeaderField { field: "datetime" })
+        ));
+        assert!(matches!(
+            XrainHeader::builder()
+                .region(Region::Tohoku)
+                .datetime(datetime)
+                .build(),
+            Err(XrainError::MissingHeaderField { field: "owner" })
+        ));
+        assert!(matches!(
+            XrainHeader::builder()
+                .owner(71)
+                .datetime(datetime)
+                .extent("4634".parse().unwrap(), "5543".parse().unwrap())
+                .build(),
+            Err(XrainError::MissingHeaderField {
+                field: "data_kind3"
+            })
+        ));
+        assert!(matches!(
+            XrainHeader::builder()
+                .owner(71)
+                .data_kind3(0x0047)
+                .datetime(datetime)
+                .extent("5543".parse().unwrap(), "4634".parse().unwrap())
+                .build(),
+            Err(XrainError::InvalidExtent { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocks() -> Result<(), XrainError> {
+        let header = Fixture::default().header();
+        let mesh = |code: &str| {
+            SecondaryMesh::new(code.parse().unwrap(), vec![XrainCell::default(); 1600]).unwrap()
+        };
+
+        let mut encoder = XrainEncoder::new(header.clone());
+        encoder.extend(["543876", "543877", "543970", "543972"].map(mesh))?;
+        assert_eq!(encoder.block_num(), 2);
+        assert_eq!(encoder.data_size(), 64 + 8 + 4 * 3200);
+        assert_eq!(encoder.header().block_num(), 2);
+
+        assert!(matches!(
+            encoder.push_block(["543870", "543872"].map(mesh)),
+            Err(XrainError::InvalidBlock { .. })
+        ));
+        assert!(matches!(
+            encoder.push_block(Vec::new()),
+            Err(XrainError::EmptyBlock { .. })
+        ));
+        // 東へ連続していても直前のブロックとはまとめない。
+        encoder.push_block(["543973"].map(mesh))?;
+        assert_eq!(encoder.block_num(), 3);
+
+        let mut encoder = XrainEncoder::new(header);
+        assert!(matches!(
+            encoder.push(SecondaryMesh::new(
+                "543870".parse().unwrap(),
+                vec![XrainCell::default(); 400]
+            )?),
+            Err(XrainError::CellCountMismatch {
+                expected: 1600,
+                found: 400,
+                ..
+            })
+        ));
+        let mut cells = vec![XrainCell::default(); 1600];
+        cells[10] = XrainCell::new(0, 0x1000);
+        assert!(matches!(
+            encoder.push(SecondaryMesh::new("543870".parse().unwrap(), cells)?),
+            Err(XrainError::CellOutOfRange { index: 10, .. })
+        ));
+        assert_eq!(encoder.block_num(), 0);
+        Ok(())
+    }
+}
@@ -4,35 +4,82 @@
 #include <ostream>
 #include <new>
 
-template<typename T = void>
-struct Option;
+/// Product stored in the file (data kind 2).
+///
+/// データ種別2
+///
+/// デコードには使わない。並びが違うプロダクトが確認できたらここで分ける。
+enum class DataKind : uint8_t {
+  /// 合成レーダー雨量
+  CompositeRain = 1,
+  /// 単一局レーダー雨量
+  SingleSiteRain = 2,
+};
+
+/// Observation date and time of a file, in JST.
+///
+/// 観測日時(日本標準時)
+/// ヘッダーの16byteのうち先頭7byteが年(2byte)、月、日、時、分、秒のBCD。残りは予備。
+struct ObservationTime {
+  uint16_t year;
+  uint8_t month;
+  uint8_t day;
+  uint8_t hour;
+  uint8_t minute;
+  uint8_t second;
+};
+
+/// The raw 16-byte system status of the header.
+///
+/// システムステータス
+/// サイトとbitの対応やbitの意味は仕様書で未確認なので、16byteをそのまま持つ。
+/// bitの番号は先頭バイトの最上位bitを0として数える。
+struct SystemStatus {
+  uint8_t bits[16];
+};
+
+/// First-level mesh code (about 80 km square), e.g. `5438`.
+///
+/// 1次メッシュコード
+/// lat:上2桁(緯度*1.5)、lon:下2桁(経度-100)
+struct PrimaryMeshCode {
+  uint8_t lat;
+  uint8_t lon;
+};
 
 /// A header of XRAIN, which explains the number of blocks, data length(size), bottom left, upper right, etc...
 ///
 /// XRAINファイルのヘッダー
 /// 詳しくはドキュメントを参照されたい。
-/// かなり未実装。
-/// TODO:まじでいつか書く
+/// ファイルから読むか、`XrainHeader::builder`で作る。
 struct XrainHeader {
   ///地整識別
   uint8_t owner;
+  ///データ種別2
+  DataKind data_kind;
   ///データ種別3
-  /// 1byte:対象エリアの地整識別コード
-  uint16_t mesh_kind;
-  ///観測日時(WIP)
-  unsigned long long datetime;
+  /// 実ファイル(関東)では0x0047で、対象エリアの地整識別コードと同じ値。読んだ値のまま持つ。
+  uint16_t data_kind3;
+  ///観測日時(JST)
+  ObservationTime datetime;
+  ///観測日時の予備9byte
+  uint8_t datetime_reserved[9];
+  ///システムステータス
+  SystemStatus system_status;
+  ///装置No.
+  uint8_t device_no;
   ///応答ステータス
   uint8_t response_status;
   ///ブロック数
   uint16_t block_num;
-  ///ファイルのサイズ
+  ///データサイズ。ヘッダーを含むファイル全体のバイト数とみなしているが未確認。
   uint32_t data_size;
   ///南西端の1次メッシュコード
-  uint8_t bottom_left_lat;
-  uint8_t bottom_left_lon;
+  PrimaryMeshCode bottom_left;
   ///北東端の1次メッシュコード
-  uint8_t top_right_lat;
-  uint8_t top_right_lon;
+  PrimaryMeshCode top_right;
+  ///予備領域
+  uint8_t reserved[10];
 };
 
 /// Has quality and rainfall data.(cf. XRAIN document)
@@ -45,28 +92,3 @@ struct XrainCell {
   ///雨量
   uint16_t strength;
 };
-
-/// XRAIN dataset
-/// It contains header and data.
-/// In future, it can be handled with gdal.
-struct CXrainDataset {
-  ///TODO:CXrainDatasetをmem::forgetした後、
-  /// XRAINheaderはどうなるんだろう。Dropされるのかな？
-  XrainHeader header;
-  ///配列のポインタ。
-  XrainCell *ptr;
-  ///The number of XrainCell.
-  uint64_t length;
-};
-
-struct CXrainResult {
-  bool status;
-  CXrainDataset data;
-};
-
-extern "C" {
-
-/// Open and get XRAIN dataset.
-Option<CXrainResult> open_ffi(const char *file_path);
-
-} // extern "C"